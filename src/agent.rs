@@ -1,45 +1,159 @@
-use crate::tic_tac_toe::Player;
-use crate::{q_matrix::Q, tic_tac_toe::Board};
+use std::ops::Range;
 
-use rand::distributions::{Distribution, Uniform};
+use crate::dyna::{Model, Transition};
+use crate::exploration::{EpsilonGreedy, ExplorationStrategy};
+use crate::opponent::{FrozenQ, Opponent, RandomOpponent};
+use crate::q_matrix::{Q, QFunction, StepSize};
+use crate::replay::ReplayBuffer;
+use crate::reward::RewardScheme;
+use crate::schedule::Schedule;
+use crate::states;
+use crate::tic_tac_toe::{Board, GameResult, Player};
+
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{Rng, SeedableRng};
+
+/// How many games `win_rate` plays when `learn_until` checks on progress
+const EVAL_GAMES: usize = 200;
 
-pub struct Agent<const N: usize> {
-    pub eps: f64,
-    pub qlearner: Q<N>,
+/// How a player has done over a number of games
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Record {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Record {
+    /// Count a game that ended in `result`, for `player`
+    pub fn add(&mut self, result: GameResult, player: Player) {
+        match (result, player) {
+            (GameResult::Tie, _) => self.draws += 1,
+            (GameResult::XWon, Player::X) | (GameResult::OWon, Player::O) => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, with a win worth 1 and a draw 0.5. 0.5 if no games were played.
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+    }
+}
+
+pub struct Agent<const N: usize, L = Q<N>> {
+    pub exploration: Box<dyn ExplorationStrategy<N> + Send>,
+    pub qlearner: L,
+    /// Dyna-Q planning: how many simulated updates from `model` to make after
+    /// every real one. Zero turns planning off.
+    pub planning_steps: usize,
+    pub model: Model<N>,
+    /// Experience replay: when set, every real step is stored, and `replay_batch`
+    /// stored steps are replayed after it
+    pub replay: Option<ReplayBuffer<N>>,
+    pub replay_batch: usize,
+    pub rewards: RewardScheme,
+    /// Every random choice the agent makes comes from here, so seeding it makes
+    /// training reproducible
+    pub rng: StdRng,
 }
 
 impl<const N: usize> Agent<N> {
     pub fn new() -> Self {
+        Agent::with_learner(Q::new())
+    }
+
+    /// Configure a tabular agent step by step
+    pub fn builder() -> AgentBuilder<N> {
+        AgentBuilder::new(Q::new())
+    }
+}
+
+impl<const N: usize> Default for Agent<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, L: QFunction<N>> Agent<N, L> {
+    /// An agent that learns with any `QFunction`, e.g. a neural network
+    pub fn with_learner(qlearner: L) -> Self {
+        // Epsilon falls linearly from 1 to 0 over training
+        let exploration = EpsilonGreedy::new(Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+        });
         Agent {
-            eps: 1.0,
-            qlearner: Q::new(),
+            exploration: Box::new(exploration),
+            qlearner,
+            planning_steps: 0,
+            model: Model::new(),
+            replay: None,
+            replay_batch: 32,
+            rewards: RewardScheme::classic(),
+            rng: StdRng::from_entropy(),
         }
     }
 
-    pub fn get_action(&self, state: Board<N>, valid_actions: &[(usize, usize)]) -> (usize, usize) {
-        // If random draw from U(0, 1) < self.eps, return a random choice from valid_actions
-        let mut rng = thread_rng();
-        let u = Uniform::from(0.0..1.0);
-        if u.sample(&mut rng) < self.eps {
-            return *valid_actions
-                .choose(&mut rng)
-                .expect("Nothing in valid_actions to select");
+    /// Update the Q matrix from a real step. With a replay buffer, also store the
+    /// step and replay a mini-batch. With planning on, also remember the step in the
+    /// model, then replay that many remembered steps.
+    fn learn_from(&mut self, transition: Transition<N>) {
+        let Transition {
+            state,
+            action,
+            next_state,
+            reward,
+            ..
+        } = transition;
+        self.qlearner.update(state, action, next_state, reward);
+
+        if let Some(replay) = &mut self.replay {
+            replay.push(transition);
+            replay.replay(&mut self.qlearner, self.replay_batch);
         }
 
-        // Otherwise, get the best action for this state if any. If None, return random
-        // choice from valid_actions
-        match self.qlearner.max_action_for_state(state) {
-            (None, _) => *valid_actions
-                .choose(&mut rng)
-                .expect("Nothing in valid_actions to select"),
-            (Some(action), _) => action,
+        if self.planning_steps == 0 {
+            return;
+        }
+
+        self.model.record(transition);
+        for _ in 0..self.planning_steps {
+            let t = self
+                .model
+                .sample(&mut self.rng)
+                .expect("Just recorded a transition");
+            self.qlearner
+                .update(t.state, t.action, t.next_state, t.reward);
         }
     }
 
+    pub fn get_action(
+        &mut self,
+        state: Board<N>,
+        valid_actions: &[(usize, usize)],
+    ) -> (usize, usize) {
+        self.exploration
+            .choose(&self.qlearner, state, valid_actions, &mut self.rng)
+    }
+
+    /// Play one game as X against random moves, learning from every move. The
+    /// random moves are seeded from the agent's `rng`.
     pub fn learn_one_game(&mut self) {
-        let mut rng = thread_rng();
+        let mut opponent = RandomOpponent::seeded(self.rng.r#gen());
+        self.learn_one_game_against(&mut opponent);
+    }
+
+    /// Play one game as X against `opponent`, learning from every move. Returns how
+    /// the game ended.
+    pub fn learn_one_game_against(&mut self, opponent: &mut dyn Opponent<N>) -> GameResult {
         let mut game = Board::<N>::new();
         let mut player = Player::X;
         loop {
@@ -49,59 +163,406 @@ impl<const N: usize> Agent<N> {
             player = player.next_player();
 
             // Update the Q matrix if the game is over
-            // May want to re-think this. It rewards the agent for ties, as well as winning
-            if winner.is_some() {
-                self.qlearner.update(state, action, game, 100.0);
-                break;
+            if let Some(res) = winner {
+                self.learn_from(Transition {
+                    state,
+                    action,
+                    next_state: game,
+                    reward: self.rewards.for_move(winner, Player::X),
+                    done: true,
+                });
+                return res;
             }
 
-            // Other player makes random action
-            let valid_actions = game.get_empty_spots();
-            let rand_action = valid_actions
-                .choose(&mut rng)
-                .expect("Failed to notice that the game was over");
-            let winner = game.make_move(player, rand_action.0, rand_action.1);
+            // Other player makes its move
+            let opp_action = opponent.choose_move(&game, player);
+            let winner = game.make_move(player, opp_action.0, opp_action.1);
             player = player.next_player();
 
-            // If the other player won (or tied the game), update the Q matrix
-            if winner.is_some() {
-                self.qlearner.update(state, action, game, -100.0);
-                break;
+            // Update the Q matrix, with the reward for the result if the other
+            // player ended the game
+            self.learn_from(Transition {
+                state,
+                action,
+                next_state: game,
+                reward: self.rewards.for_move(winner, Player::X),
+                done: winner.is_some(),
+            });
+            if let Some(res) = winner {
+                return res;
             }
-
-            // Update Q matrix with reward of 0
-            self.qlearner.update(state, action, game, 0.0);
         }
     }
 
+    /// Learn over `n` games against random moves seeded from the agent's `rng`
     pub fn learn(&mut self, n: usize) {
-        let exploration_decrease = 1.0 / (n as f64);
-        for _ in 0..n {
-            self.learn_one_game();
-            self.eps -= exploration_decrease;
+        let mut opponent = RandomOpponent::seeded(self.rng.r#gen());
+        self.learn_against(n, &mut opponent);
+    }
+
+    /// Learn over `n` games against `opponent`, exploring less as it goes
+    pub fn learn_against(&mut self, n: usize, opponent: &mut dyn Opponent<N>) {
+        self.learn_episodes(0..n, n, opponent);
+        self.report_explored_states();
+    }
+
+    /// Learn from just `episodes` of `total`, so schedules pick up where an earlier
+    /// call left off
+    pub fn learn_episodes(
+        &mut self,
+        episodes: Range<usize>,
+        total: usize,
+        opponent: &mut dyn Opponent<N>,
+    ) {
+        for episode in episodes {
+            self.learn_episode(episode, total, opponent);
         }
+    }
+
+    /// Learn from game `episode` of `total`, and return how it ended
+    pub fn learn_episode(
+        &mut self,
+        episode: usize,
+        total: usize,
+        opponent: &mut dyn Opponent<N>,
+    ) -> GameResult {
+        self.exploration.start_episode(episode, total);
+        self.qlearner.start_episode(episode, total);
+        self.learn_one_game_against(opponent)
+    }
 
+    /// Learn against `opponent` until the agent wins at least `target` of its games,
+    /// checking every `check_every` games. Returns how many games that took, or
+    /// None if the target was not reached in `max_episodes`.
+    pub fn learn_until(
+        &mut self,
+        target: f64,
+        max_episodes: usize,
+        check_every: usize,
+        opponent: &mut dyn Opponent<N>,
+    ) -> Option<usize> {
+        for episode in 0..max_episodes {
+            self.exploration.start_episode(episode, max_episodes);
+            self.qlearner.start_episode(episode, max_episodes);
+            self.learn_one_game_against(opponent);
+            let n_played = episode + 1;
+            if n_played % check_every.max(1) == 0 && self.win_rate(EVAL_GAMES, opponent) >= target {
+                self.report_explored_states();
+                return Some(n_played);
+            }
+        }
+        self.report_explored_states();
+        None
+    }
+
+    /// The fraction of `games` won against `opponent`, always playing the best
+    /// known move
+    pub fn win_rate(&mut self, games: usize, opponent: &mut dyn Opponent<N>) -> f64 {
+        self.record_against(games, opponent).wins as f64 / games.max(1) as f64
+    }
+
+    /// Stop learning, and play the best known moves from now on with the agent's
+    /// rewards and random number generator
+    pub fn freeze(self) -> FrozenQ<N, L> {
+        FrozenQ {
+            q: self.qlearner,
+            rewards: self.rewards,
+            rng: self.rng,
+        }
+    }
+
+    /// Wins, draws and losses over `games` against `opponent`, always playing the
+    /// best known move
+    pub fn record_against(&mut self, games: usize, opponent: &mut dyn Opponent<N>) -> Record {
+        let mut record = Record::default();
+        for _ in 0..games {
+            let mut game = Board::<N>::new();
+            let mut player = Player::X;
+            let result = loop {
+                let action = self.best_action(game);
+                if let Some(res) = game.make_move(player, action.0, action.1) {
+                    break res;
+                }
+                player = player.next_player();
+
+                let opp_action = opponent.choose_move(&game, player);
+                if let Some(res) = game.make_move(player, opp_action.0, opp_action.1) {
+                    break res;
+                }
+                player = player.next_player();
+            };
+            record.add(result, Player::X);
+        }
+        record
+    }
+
+    /// The best known move in `state`, or a random one if there is none
+    pub fn best_action(&mut self, state: Board<N>) -> (usize, usize) {
+        match self.qlearner.max_action_for_state(state) {
+            (Some(action), _) => action,
+            (None, _) => *state
+                .get_empty_spots()
+                .choose(&mut self.rng)
+                .expect("Failed to notice that the game was over"),
+        }
+    }
+
+    fn report_explored_states(&self) {
         // Check if all states have been visited at least once
-        let n_explored_states = self.qlearner.values.len();
-        if n_explored_states != 0 {
+        let n_explored_states = self.qlearner.n_states();
+        if n_explored_states == 0 {
+            return;
+        }
+        // Bigger boards have far too many positions to count every time
+        if N <= 3 {
+            let reachable = states::decision_states(Board::<N>::new()).len();
+            println!(
+                "Have visited {} of the {} positions X can move in ({:.1}%)",
+                n_explored_states,
+                reachable,
+                100.0 * n_explored_states as f64 / reachable as f64
+            );
+        } else {
             println!("Have visited {} states at least once", n_explored_states);
         }
     }
 }
 
+/// A trained agent plays its best known moves
+impl<const N: usize, L: QFunction<N>> Opponent<N> for Agent<N, L> {
+    fn choose_move(&mut self, board: &Board<N>, _player: Player) -> (usize, usize) {
+        self.best_action(*board)
+    }
+
+    fn annotate(
+        &self,
+        board: &Board<N>,
+        _player: Player,
+        action: (usize, usize),
+    ) -> Option<String> {
+        Some(format!("q={:.3}", self.qlearner.get(*board, action)))
+    }
+
+    /// As `QFunction::move_values`, with the agent's rewards
+    fn move_values(&self, board: &Board<N>, player: Player) -> Option<Vec<((usize, usize), f64)>> {
+        Some(self.qlearner.move_values(*board, player, &self.rewards))
+    }
+}
+
+/// Builds an `Agent` one setting at a time. Anything not set keeps the default
+/// from `Agent::with_learner`.
+///
+/// ```
+/// use qtictactoe::{Agent, Schedule};
+/// use qtictactoe::Boltzmann;
+///
+/// let agent = Agent::<3>::builder()
+///     .alpha(Schedule::Constant(0.1))
+///     .discount(Schedule::Constant(0.9))
+///     .exploration(Boltzmann::new(Schedule::Constant(10.0)))
+///     .planning_steps(5)
+///     .seed(42)
+///     .build();
+/// assert_eq!(0.1, agent.qlearner.alpha);
+/// ```
+pub struct AgentBuilder<const N: usize, L = Q<N>> {
+    agent: Agent<N, L>,
+}
+
+impl<const N: usize, L: QFunction<N>> AgentBuilder<N, L> {
+    /// Start from an agent that learns with `qlearner`
+    pub fn new(qlearner: L) -> Self {
+        AgentBuilder {
+            agent: Agent::with_learner(qlearner),
+        }
+    }
+
+    pub fn exploration(
+        mut self,
+        exploration: impl ExplorationStrategy<N> + Send + 'static,
+    ) -> Self {
+        self.agent.exploration = Box::new(exploration);
+        self
+    }
+
+    pub fn rewards(mut self, rewards: RewardScheme) -> Self {
+        self.agent.rewards = rewards;
+        self
+    }
+
+    /// Dyna-Q planning updates after every real one
+    pub fn planning_steps(mut self, steps: usize) -> Self {
+        self.agent.planning_steps = steps;
+        self
+    }
+
+    /// Replay `batch` steps from `buffer` after every real one
+    pub fn replay(mut self, buffer: ReplayBuffer<N>, batch: usize) -> Self {
+        self.agent.replay = Some(buffer);
+        self.agent.replay_batch = batch;
+        self
+    }
+
+    /// Seed every random choice the agent makes
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.agent.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn build(self) -> Agent<N, L> {
+        self.agent
+    }
+}
+
+impl<const N: usize> AgentBuilder<N> {
+    /// The learning rate. A constant schedule fixes it for all of training
+    pub fn alpha(mut self, alpha: Schedule) -> Self {
+        self.agent.qlearner.alpha = alpha.value(0, 1);
+        self.agent.qlearner.alpha_schedule = Some(alpha);
+        self
+    }
+
+    /// The discount on future rewards
+    pub fn discount(mut self, discount: Schedule) -> Self {
+        self.agent.qlearner.discount = discount.value(0, 1);
+        self.agent.qlearner.discount_schedule = Some(discount);
+        self
+    }
+
+    pub fn step_size(mut self, step_size: StepSize) -> Self {
+        self.agent.qlearner.step_size = step_size;
+        self
+    }
+
+    /// The value of moves never tried. High values make the agent try everything
+    pub fn initial_value(mut self, value: f64) -> Self {
+        self.agent.qlearner.initial_value = value;
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::exploration::Ucb1;
+    use crate::mcts::{Budget, Mcts};
+    use crate::replay::Sampling;
 
     #[test]
     fn test_learn_one_game() {
-        let mut agent = Agent::<3>::new();
+        let mut agent = Agent::<3>::builder().seed(1).build();
         agent.learn_one_game();
     }
 
+    #[test]
+    fn test_learn_against_mcts() {
+        let mut agent = Agent::<3>::builder().seed(2).build();
+        let mut mcts = Mcts::new(Budget::Iterations(50));
+        mcts.rng = StdRng::seed_from_u64(0);
+        agent.learn_against(20, &mut mcts);
+        assert!(agent.qlearner.n_states() > 0);
+    }
+
+    #[test]
+    fn test_move_values() {
+        let mut agent = Agent::<3>::builder().seed(3).build();
+        // O can win at (1, 2), and otherwise X likes (2, 2) after O's move
+        let board: Board<3> = "XX./OO./X.. o".parse().unwrap();
+        let mut after = board;
+        after.make_move(Player::O, 2, 1);
+        agent
+            .qlearner
+            .values
+            .entry(after)
+            .or_default()
+            .insert((0, 2), 40.0);
+
+        let values = agent.move_values(&board, Player::O).unwrap();
+        let value = |action| values.iter().find(|&&(a, _)| a == action).unwrap().1;
+        assert_eq!(100.0, value((1, 2)));
+        assert_eq!(-40.0, value((2, 1)));
+        assert_eq!(4, values.len());
+    }
+
+    #[test]
+    fn test_dyna_planning() {
+        let mut agent = Agent::<3>::builder().seed(4).build();
+        agent.planning_steps = 5;
+        agent.learn(100);
+        assert!(agent.model.n_transitions() > 0);
+        assert!(
+            agent.model.n_transitions() <= agent.qlearner.values.values().map(|a| a.len()).sum()
+        );
+    }
+
+    #[test]
+    fn test_learn_until() {
+        let mut agent = Agent::<3>::builder().seed(5).build();
+        agent.planning_steps = 5;
+        let episodes = agent.learn_until(0.5, 5_000, 500, &mut RandomOpponent::seeded(0));
+        assert!(episodes.is_some());
+    }
+
+    #[test]
+    fn test_experience_replay() {
+        let mut agent = Agent::<3>::builder().seed(6).build();
+        agent.replay = Some(ReplayBuffer::new(100, Sampling::prioritised(), 0));
+        agent.replay_batch = 4;
+        agent.learn(50);
+        assert_eq!(100, agent.replay.as_ref().unwrap().len());
+    }
+
+    #[test]
+    fn test_learn_with_ucb1() {
+        let mut agent = Agent::<3>::builder().seed(7).build();
+        agent.exploration = Box::new(Ucb1::new(10.0));
+        agent.learn(100);
+        assert!(agent.qlearner.n_states() > 0);
+    }
+
+    #[test]
+    fn test_unit_rewards() {
+        let mut agent = Agent::<3>::builder().seed(8).build();
+        agent.rewards = RewardScheme::unit();
+        agent.learn(200);
+        // Nothing can be worth more than a win, or less than a loss
+        for actions in agent.qlearner.values.values() {
+            for &value in actions.values() {
+                assert!((0.0..=1.0).contains(&value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_q_table() {
+        let train = |seed| {
+            let mut agent = Agent::<3>::builder().seed(seed).build();
+            agent.planning_steps = 2;
+            agent.learn(500);
+            agent.qlearner
+        };
+        let bits = |q: &Q<3>| {
+            let mut bits: Vec<_> = q
+                .values
+                .iter()
+                .flat_map(|(state, actions)| {
+                    actions
+                        .iter()
+                        .map(move |(action, value)| (*state, *action, value.to_bits()))
+                })
+                .collect();
+            bits.sort_by_key(|&(state, action, _)| (state.to_string(), action));
+            bits
+        };
+        let (a, b) = (train(7), train(7));
+        assert_eq!(bits(&a), bits(&b));
+        assert_eq!(a.visits, b.visits);
+        assert_ne!(bits(&a), bits(&train(8)));
+    }
+
     #[test]
     fn test_learn() {
-        let mut agent = Agent::<3>::new();
+        let mut agent = Agent::<3>::builder().seed(9).build();
         agent.learn(1_000);
     }
 }
@@ -0,0 +1,515 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::error::{Error, Result};
+use crate::mlp::Mlp;
+use crate::opponent::Opponent;
+use crate::tic_tac_toe::{Board, GameResult, Piece, Player};
+
+/// Predicts, for the player to move, a probability for every cell (the policy)
+/// and the expected outcome of the game in [-1, 1] (the value)
+#[derive(Debug, Clone)]
+pub struct PolicyValueNet<const N: usize> {
+    pub policy: Mlp,
+    pub value: Mlp,
+}
+
+impl<const N: usize> PolicyValueNet<N> {
+    /// An error if `hidden` is zero
+    pub fn new(hidden: usize, rng: &mut impl Rng) -> Result<Self> {
+        Ok(PolicyValueNet {
+            policy: Mlp::new(&[3 * N * N, hidden, N * N], rng)?,
+            value: Mlp::new(&[3 * N * N, hidden, 1], rng)?,
+        })
+    }
+
+    /// One-hot encode a board with three inputs per cell: empty, `player`'s piece
+    /// and the other player's piece. This means the same network plays both sides.
+    fn encode(board: &Board<N>, player: Player) -> Vec<f64> {
+        let own = player.piece();
+        let mut input = vec![0.0; 3 * N * N];
+        for (idx, &piece) in board.board.iter().flatten().enumerate() {
+            let offset = match piece {
+                Piece::Empty => 0,
+                p if p == own => 1,
+                _ => 2,
+            };
+            input[3 * idx + offset] = 1.0;
+        }
+        input
+    }
+
+    /// Softmax of the policy over the empty cells, which is zero on the occupied ones
+    fn masked_softmax(board: &Board<N>, logits: &[f64]) -> Vec<f64> {
+        let legal: Vec<bool> = board
+            .board
+            .iter()
+            .flatten()
+            .map(|&p| p == Piece::Empty)
+            .collect();
+        let max = logits
+            .iter()
+            .zip(&legal)
+            .filter(|(_, l)| **l)
+            .map(|(x, _)| *x)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let exps: Vec<f64> = logits
+            .iter()
+            .zip(&legal)
+            .map(|(x, &l)| if l { (x - max).exp() } else { 0.0 })
+            .collect();
+        let total: f64 = exps.iter().sum();
+        exps.iter().map(|e| e / total).collect()
+    }
+
+    /// The move probabilities (indexed by `row * N + col`) and value of `board`,
+    /// for `player` to move
+    pub fn predict(&self, board: &Board<N>, player: Player) -> (Vec<f64>, f64) {
+        let input = Self::encode(board, player);
+        let priors = Self::masked_softmax(board, &self.policy.forward(&input));
+        let value = self.value.forward(&input)[0].tanh();
+        (priors, value)
+    }
+
+    /// One optimisation step towards the search's visit distribution `policy` and the
+    /// final `outcome` of the game for `player`
+    pub fn train(&mut self, board: &Board<N>, player: Player, policy: &[f64], outcome: f64) {
+        let input = Self::encode(board, player);
+
+        // Cross entropy through a softmax has gradient `predicted - target`
+        let predicted = Self::masked_softmax(board, &self.policy.forward(&input));
+        let policy_grad: Vec<f64> = predicted.iter().zip(policy).map(|(p, t)| p - t).collect();
+        self.policy.backward(&input, &policy_grad);
+
+        // Squared error through the tanh
+        let value = self.value.forward(&input)[0].tanh();
+        self.value
+            .backward(&input, &[(value - outcome) * (1.0 - value * value)]);
+    }
+}
+
+/// A node in the search tree. `value_sum` is from the point of view of the player
+/// who made the move leading to this node.
+struct Node<const N: usize> {
+    board: Board<N>,
+    to_move: Player,
+    result: Option<GameResult>,
+    prior: f64,
+    visits: f64,
+    value_sum: f64,
+    children: Vec<((usize, usize), usize)>,
+}
+
+impl<const N: usize> Node<N> {
+    fn new(board: Board<N>, to_move: Player, result: Option<GameResult>, prior: f64) -> Self {
+        Node {
+            board,
+            to_move,
+            result,
+            prior,
+            visits: 0.0,
+            value_sum: 0.0,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Tree search guided by a `PolicyValueNet` (PUCT). Rather than playing games out
+/// at random, leaves are valued by the network and moves are weighted by its policy.
+pub fn search<const N: usize>(
+    net: &PolicyValueNet<N>,
+    board: &Board<N>,
+    player: Player,
+    simulations: usize,
+    c_puct: f64,
+) -> Vec<f64> {
+    let mut tree = vec![Node::new(*board, player, None, 1.0)];
+
+    // The first simulation only expands the root
+    for _ in 0..=simulations {
+        // Selection
+        let mut path = vec![0];
+        let mut node = 0;
+        while !tree[node].children.is_empty() {
+            let sqrt_visits = tree[node].visits.sqrt();
+            let puct = |child: usize| {
+                let c = &tree[child];
+                let q = if c.visits > 0.0 {
+                    c.value_sum / c.visits
+                } else {
+                    0.0
+                };
+                q + c_puct * c.prior * sqrt_visits / (1.0 + c.visits)
+            };
+            node = tree[node]
+                .children
+                .iter()
+                .map(|&(_, child)| child)
+                .max_by(|&a, &b| puct(a).total_cmp(&puct(b)))
+                .expect("Loop only runs while there are children");
+            path.push(node);
+        }
+
+        // Evaluation, and expansion of unfinished games. `value` is for the player
+        // who moved into `node`
+        let value = match tree[node].result {
+            Some(GameResult::Tie) => 0.0,
+            // Whoever made the last move won
+            Some(_) => 1.0,
+            None => {
+                let (priors, value) = net.predict(&tree[node].board, tree[node].to_move);
+                let mover = tree[node].to_move;
+                for (row, col) in tree[node].board.get_empty_spots() {
+                    let mut next = tree[node].board;
+                    let result = next.make_move(mover, row, col);
+                    tree.push(Node::new(
+                        next,
+                        mover.next_player(),
+                        result,
+                        priors[row * N + col],
+                    ));
+                    let child = tree.len() - 1;
+                    tree[node].children.push(((row, col), child));
+                }
+                -value
+            }
+        };
+
+        // Backpropagation, flipping the sign at every level
+        let mut value = value;
+        for &idx in path.iter().rev() {
+            tree[idx].visits += 1.0;
+            tree[idx].value_sum += value;
+            value = -value;
+        }
+    }
+
+    let mut visits = vec![0.0; N * N];
+    for &((row, col), child) in &tree[0].children {
+        visits[row * N + col] = tree[child].visits;
+    }
+    visits
+}
+
+/// Pick an empty cell of `board` from search visit counts, either in proportion to
+/// them or the most visited
+fn pick_move<const N: usize>(
+    board: &Board<N>,
+    visits: &[f64],
+    sample: bool,
+    rng: &mut impl Rng,
+) -> (usize, usize) {
+    let legal = board.get_empty_spots();
+    let visits_of = |&(row, col): &(usize, usize)| visits[row * N + col];
+    if sample && let Ok(dist) = WeightedIndex::new(legal.iter().map(visits_of)) {
+        return legal[dist.sample(rng)];
+    }
+    *legal
+        .iter()
+        .max_by(|a, b| visits_of(a).total_cmp(&visits_of(b)))
+        .expect("The game is not over")
+}
+
+/// One position from self-play: the search's visit distribution, and how the
+/// game ended for the player to move
+pub struct Example<const N: usize> {
+    pub board: Board<N>,
+    pub player: Player,
+    pub policy: Vec<f64>,
+    pub outcome: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AlphaZeroConfig {
+    pub generations: usize,
+    pub games_per_generation: usize,
+    /// Search simulations per move
+    pub simulations: usize,
+    /// How strongly the policy steers the search towards unvisited moves
+    pub c_puct: f64,
+    /// Passes over each generation's self-play positions
+    pub epochs: usize,
+    /// Games between a newly trained network and the current best
+    pub gating_games: usize,
+    /// Fraction of the gating games' points needed to replace the best network
+    pub gating_threshold: f64,
+    /// Moves are sampled in proportion to visit counts for this many moves of each
+    /// game, then the most visited move is played
+    pub temperature_moves: usize,
+}
+
+impl Default for AlphaZeroConfig {
+    fn default() -> Self {
+        AlphaZeroConfig {
+            generations: 10,
+            games_per_generation: 50,
+            simulations: 100,
+            c_puct: 1.5,
+            epochs: 4,
+            gating_games: 20,
+            gating_threshold: 0.55,
+            temperature_moves: 2,
+        }
+    }
+}
+
+/// How one generation of training went
+#[derive(Debug, Clone, Copy)]
+pub struct GenerationReport {
+    pub generation: usize,
+    pub examples: usize,
+    /// Points per game the new network scored against the best (win 1, tie 0.5)
+    pub candidate_score: f64,
+    pub promoted: bool,
+}
+
+/// Self-play training of a `PolicyValueNet` guided by tree search, keeping only
+/// networks that beat the best one so far
+pub struct AlphaZero<const N: usize> {
+    pub config: AlphaZeroConfig,
+    pub best: PolicyValueNet<N>,
+    /// Where every game starts, e.g. a board made with `Board::with_win_length`
+    pub start: Board<N>,
+    /// Used when playing as an `Opponent`
+    pub rng: StdRng,
+}
+
+impl<const N: usize> AlphaZero<N> {
+    /// An error if `config` searches with no simulations, which would leave every
+    /// move unvisited, or `hidden` is zero
+    pub fn new(
+        config: AlphaZeroConfig,
+        hidden: usize,
+        start: Board<N>,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        if config.simulations == 0 {
+            return Err(Error::Config(
+                "Alpha-zero needs at least one search simulation per move".to_string(),
+            ));
+        }
+        Ok(AlphaZero {
+            config,
+            best: PolicyValueNet::new(hidden, rng)?,
+            start,
+            rng: StdRng::seed_from_u64(rng.r#gen()),
+        })
+    }
+
+    /// Play one game of `net` against itself
+    pub fn self_play_game(&self, net: &PolicyValueNet<N>, rng: &mut impl Rng) -> Vec<Example<N>> {
+        let mut game = self.start;
+        let mut player = game.player_to_move();
+        let mut examples = Vec::new();
+        let mut n_moves = 0;
+        loop {
+            let visits = search(
+                net,
+                &game,
+                player,
+                self.config.simulations,
+                self.config.c_puct,
+            );
+            let total: f64 = visits.iter().sum();
+            examples.push(Example {
+                board: game,
+                player,
+                policy: visits.iter().map(|v| v / total).collect(),
+                outcome: 0.0,
+            });
+
+            let sample = n_moves < self.config.temperature_moves;
+            let (row, col) = pick_move(&game, &visits, sample, rng);
+            n_moves += 1;
+            if let Some(result) = game.make_move(player, row, col) {
+                for ex in &mut examples {
+                    ex.outcome = match (result, ex.player) {
+                        (GameResult::Tie, _) => 0.0,
+                        (GameResult::XWon, Player::X) | (GameResult::OWon, Player::O) => 1.0,
+                        _ => -1.0,
+                    };
+                }
+                return examples;
+            }
+            player = player.next_player();
+        }
+    }
+
+    /// Points per game `candidate` scores against `incumbent`, taking turns to
+    /// move first
+    pub fn play_match(
+        &self,
+        candidate: &PolicyValueNet<N>,
+        incumbent: &PolicyValueNet<N>,
+        games: usize,
+        rng: &mut impl Rng,
+    ) -> f64 {
+        let mut points = 0.0;
+        for game_num in 0..games {
+            let candidate_side = if game_num % 2 == 0 {
+                Player::X
+            } else {
+                Player::O
+            };
+            let mut game = self.start;
+            let mut player = game.player_to_move();
+            let mut n_moves = 0;
+            let result = loop {
+                let net = if player == candidate_side {
+                    candidate
+                } else {
+                    incumbent
+                };
+                let visits = search(
+                    net,
+                    &game,
+                    player,
+                    self.config.simulations,
+                    self.config.c_puct,
+                );
+                let sample = n_moves < self.config.temperature_moves;
+                let (row, col) = pick_move(&game, &visits, sample, rng);
+                n_moves += 1;
+                if let Some(result) = game.make_move(player, row, col) {
+                    break result;
+                }
+                player = player.next_player();
+            };
+            points += match (result, candidate_side) {
+                (GameResult::Tie, _) => 0.5,
+                (GameResult::XWon, Player::X) | (GameResult::OWon, Player::O) => 1.0,
+                _ => 0.0,
+            };
+        }
+        points / games.max(1) as f64
+    }
+
+    /// Run one generation: self-play with the best network, train a copy of it on
+    /// those games, and promote the copy if it wins the gating match
+    pub fn train_generation(&mut self, generation: usize, rng: &mut impl Rng) -> GenerationReport {
+        let mut examples = Vec::new();
+        for _ in 0..self.config.games_per_generation {
+            examples.extend(self.self_play_game(&self.best, rng));
+        }
+
+        let mut candidate = self.best.clone();
+        for _ in 0..self.config.epochs {
+            for ex in &examples {
+                candidate.train(&ex.board, ex.player, &ex.policy, ex.outcome);
+            }
+        }
+
+        let candidate_score =
+            self.play_match(&candidate, &self.best, self.config.gating_games, rng);
+        let promoted = candidate_score >= self.config.gating_threshold;
+        if promoted {
+            self.best = candidate;
+        }
+        GenerationReport {
+            generation,
+            examples: examples.len(),
+            candidate_score,
+            promoted,
+        }
+    }
+
+    /// Run every generation in the config, reporting on each as it finishes
+    pub fn train(&mut self, rng: &mut impl Rng, mut report: impl FnMut(&GenerationReport)) {
+        for generation in 0..self.config.generations {
+            report(&self.train_generation(generation, rng));
+        }
+    }
+}
+
+impl<const N: usize> Opponent<N> for AlphaZero<N> {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        let visits = search(
+            &self.best,
+            board,
+            player,
+            self.config.simulations,
+            self.config.c_puct,
+        );
+        pick_move(board, &visits, false, &mut self.rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_predict_masks_occupied() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let net = PolicyValueNet::<3>::new(8, &mut rng).unwrap();
+        let mut b = Board::<3>::new();
+        b.board[0][0] = Piece::X;
+        let (priors, value) = net.predict(&b, Player::O);
+        assert_eq!(0.0, priors[0]);
+        assert!((priors.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((-1.0..=1.0).contains(&value));
+    }
+
+    #[test]
+    fn test_search_finds_win() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let net = PolicyValueNet::<3>::new(8, &mut rng).unwrap();
+        let mut b = Board::<3>::new();
+        b.board[0][0] = Piece::X;
+        b.board[0][1] = Piece::X;
+        b.board[1][0] = Piece::O;
+        b.board[1][1] = Piece::O;
+        let visits = search(&net, &b, Player::X, 200, 1.5);
+        assert_eq!((0, 2), pick_move(&b, &visits, false, &mut rng));
+    }
+
+    #[test]
+    fn test_pick_move_is_legal() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let b: Board<3> = "XX./OO./... x".parse().unwrap();
+        // Nothing visited, as with no simulations
+        let visits = [0.0; 9];
+        for sample in [false, true] {
+            let (row, col) = pick_move(&b, &visits, sample, &mut rng);
+            assert_eq!(Piece::Empty, b.board[row][col]);
+        }
+        let config = AlphaZeroConfig {
+            simulations: 0,
+            ..AlphaZeroConfig::default()
+        };
+        assert!(AlphaZero::<3>::new(config, 8, b, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_self_play_outcomes() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let az =
+            AlphaZero::<3>::new(AlphaZeroConfig::default(), 8, Board::new(), &mut rng).unwrap();
+        let examples = az.self_play_game(&az.best, &mut rng);
+        assert!(examples.len() >= 5);
+        // Consecutive positions are seen by opposite players
+        let last = examples.len() - 1;
+        assert_eq!(examples[last].outcome, -examples[last - 1].outcome);
+    }
+
+    #[test]
+    fn test_train_generation() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let config = AlphaZeroConfig {
+            generations: 1,
+            games_per_generation: 2,
+            simulations: 10,
+            epochs: 1,
+            gating_games: 2,
+            ..AlphaZeroConfig::default()
+        };
+        let mut az = AlphaZero::<4>::new(config, 8, Board::with_win_length(3), &mut rng).unwrap();
+        let mut reports = Vec::new();
+        az.train(&mut rng, |r| reports.push(*r));
+        assert_eq!(1, reports.len());
+        assert!(reports[0].examples > 0);
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt;
+
+use crate::error::Result;
+use crate::opponent::{Entry, Outcome, PerfectPlay};
+use crate::q_matrix::{Q, QFunction};
+use crate::reward::RewardScheme;
+use crate::tic_tac_toe::{Board, Piece, Player};
+
+/// Everything known about one move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveAnalysis {
+    pub action: (usize, usize),
+    /// What the learner thinks the move is worth, as `QFunction::move_values` gives
+    /// it: the learned value for X, and a look one move ahead for O
+    pub q: f64,
+    /// How many times the learner updated the values this rests on: the move's
+    /// own for X, and those of every reply in the position it leaves for O
+    pub visits: u32,
+    /// How the move turns out with perfect play
+    pub perfect: Entry,
+}
+
+/// Every legal move in a position, what a Q table thinks of it and how it really
+/// turns out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis<const N: usize> {
+    pub board: Board<N>,
+    /// Legal moves, row by row
+    pub moves: Vec<MoveAnalysis>,
+    /// The move the learner would play, or None if it has no single best move and
+    /// would pick at random
+    pub chosen: Option<(usize, usize)>,
+}
+
+/// Look at every legal move in `board`, with the values `q` learned with `rewards`
+/// and the verdicts of `oracle`. Agents learn as X, so when O is to move each move
+/// is valued by looking one move ahead, the way `FrozenQ` plays. An error if
+/// `oracle` does not know how the moves turn out.
+pub fn analyze<const N: usize>(
+    q: &Q<N>,
+    board: &Board<N>,
+    rewards: &RewardScheme,
+    oracle: &mut impl PerfectPlay<N>,
+) -> Result<Analysis<N>> {
+    let player = board.player_to_move();
+    let values = q.move_values(*board, player, rewards);
+    let visits = |action: (usize, usize)| match player {
+        Player::X => q.visits.get(&(*board, action)).copied().unwrap_or(0),
+        Player::O => {
+            let mut next = *board;
+            next.make_move(player, action.0, action.1);
+            next.get_empty_spots()
+                .into_iter()
+                .filter_map(|reply| q.visits.get(&(next, reply)))
+                .sum()
+        }
+    };
+    let moves = oracle
+        .perfect_moves(board, player)?
+        .into_iter()
+        .map(|(action, perfect)| MoveAnalysis {
+            action,
+            q: values
+                .iter()
+                .find(|&&(a, _)| a == action)
+                .map_or(q.initial_value, |&(_, v)| v),
+            visits: visits(action),
+            perfect,
+        })
+        .collect();
+    let chosen = match player {
+        Player::X => q.max_action_for_state(*board).0,
+        Player::O => match best_valued(&values)[..] {
+            [action] => Some(action),
+            _ => None,
+        },
+    };
+    Ok(Analysis {
+        board: *board,
+        moves,
+        chosen,
+    })
+}
+
+impl<const N: usize> Analysis<N> {
+    /// The moves that do as well as possible with perfect play
+    pub fn best_moves(&self) -> Vec<(usize, usize)> {
+        let best = self.moves.iter().map(|m| m.perfect.key()).max();
+        self.moves
+            .iter()
+            .filter(|m| Some(m.perfect.key()) == best)
+            .map(|m| m.action)
+            .collect()
+    }
+
+    /// The board with each empty cell showing its learned value and how it turns
+    /// out, e.g. `12.5 W3` for a win in three moves
+    pub fn heatmap(&self) -> String {
+        grid(&self.board, |action| {
+            let m = self.moves.iter().find(|m| m.action == action)?;
+            let verdict = match m.perfect.outcome {
+                Outcome::Win => 'W',
+                Outcome::Draw => 'D',
+                Outcome::Loss => 'L',
+            };
+            Some((format!("{:.1} {verdict}{}", m.q, m.perfect.distance), None))
+        })
+    }
+}
+
+/// The board with each empty cell showing what `values` says its move is worth,
+/// as `Opponent::move_values` gives them. If `colour`, the values are coloured with
+/// ANSI codes by where they fall between the worst and the best: red in the bottom
+/// third, yellow in the middle and green at the top, with the best in bold.
+pub fn value_map<const N: usize>(
+    board: &Board<N>,
+    values: &[((usize, usize), f64)],
+    colour: bool,
+) -> String {
+    let (low, high) = values.iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(low, high), &(_, v)| (low.min(v), high.max(v)),
+    );
+    grid(board, |action| {
+        let &(_, value) = values.iter().find(|&&(a, _)| a == action)?;
+        let code = colour.then(|| {
+            let scaled = if high > low {
+                (value - low) / (high - low)
+            } else {
+                1.0
+            };
+            match scaled {
+                s if s >= 1.0 => "1;32",
+                s if s >= 2.0 / 3.0 => "32",
+                s if s >= 1.0 / 3.0 => "33",
+                _ => "31",
+            }
+        });
+        Some((format!("{value:.1}"), code))
+    })
+}
+
+/// The moves `values` rates highest
+pub fn best_valued(values: &[((usize, usize), f64)]) -> Vec<(usize, usize)> {
+    let best = values
+        .iter()
+        .map(|&(_, v)| v)
+        .fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .filter(|&&(_, v)| v == best)
+        .map(|&(a, _)| a)
+        .collect()
+}
+
+/// `board` laid out like its `Display`, but with each empty cell showing the text
+/// `cell` gives it, right aligned, in the ANSI colour it gives if any. Occupied
+/// cells, and empty ones `cell` has nothing for, show the piece.
+fn grid<const N: usize>(
+    board: &Board<N>,
+    cell: impl Fn((usize, usize)) -> Option<(String, Option<&'static str>)>,
+) -> String {
+    let cells: Vec<Vec<(String, Option<&str>)>> = (0..N)
+        .map(|row| {
+            (0..N)
+                .map(|col| {
+                    let piece = board.board[row][col];
+                    match piece {
+                        Piece::Empty => cell((row, col)),
+                        _ => None,
+                    }
+                    .unwrap_or_else(|| (piece.to_string(), None))
+                })
+                .collect()
+        })
+        .collect();
+    let width = cells
+        .iter()
+        .flatten()
+        .map(|(text, _)| text.len())
+        .max()
+        .unwrap_or(1);
+
+    let mut out = String::from(" ");
+    for col in 0..N {
+        out.push_str(&format!(" {col:>width$}"));
+    }
+    out.push('\n');
+    for (row, texts) in cells.iter().enumerate() {
+        out.push_str(&row.to_string());
+        for (text, code) in texts {
+            match code {
+                Some(code) => out.push_str(&format!(" \x1b[{code}m{text:>width$}\x1b[0m")),
+                None => out.push_str(&format!(" {text:>width$}")),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A table of every move, marking the learner's choice and the best moves, then the
+/// heatmap
+impl<const N: usize> fmt::Display for Analysis<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:?} to move", self.board.player_to_move())?;
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>7} {:>12}",
+            "move", "q", "visits", "perfect play"
+        )?;
+        let best = self.best_moves();
+        for m in &self.moves {
+            write!(
+                f,
+                "{:>6} {:>10.3} {:>7} {:>12}",
+                format!("{},{}", m.action.0, m.action.1),
+                m.q,
+                m.visits,
+                m.perfect.to_string()
+            )?;
+            if best.contains(&m.action) {
+                write!(f, "  best")?;
+            }
+            if self.chosen == Some(m.action) {
+                write!(f, "  <- learner")?;
+            }
+            writeln!(f)?;
+        }
+        if self.chosen.is_none() {
+            writeln!(f, "The learner has no move here, so would play at random")?;
+        }
+        write!(f, "\n{}", self.heatmap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::Solver;
+
+    #[test]
+    fn test_analyze() {
+        // X can win at (0, 2), but has learned to like (2, 2)
+        let board: Board<3> = "XX./OO./... x".parse().unwrap();
+        let mut q = Q::new();
+        q.values.entry(board).or_default().insert((2, 2), 5.0);
+        q.visits.insert((board, (2, 2)), 7);
+
+        let analysis = analyze(&q, &board, &RewardScheme::classic(), &mut Solver::new(0)).unwrap();
+        assert_eq!(5, analysis.moves.len());
+        assert_eq!(Some((2, 2)), analysis.chosen);
+        assert_eq!(vec![(0, 2)], analysis.best_moves());
+
+        let liked = analysis.moves.iter().find(|m| m.action == (2, 2)).unwrap();
+        assert_eq!(
+            (5.0, 7, Outcome::Loss),
+            (liked.q, liked.visits, liked.perfect.outcome)
+        );
+        let text = analysis.to_string();
+        assert!(text.contains("<- learner"));
+        assert!(analysis.heatmap().contains("0.0 W1"));
+    }
+
+    #[test]
+    fn test_analyze_as_o() {
+        let mut agent = crate::Agent::<3>::builder().seed(3).build();
+        agent.learn(20_000);
+        let path = std::env::temp_dir().join("qtictactoe_test_analyze.q");
+        agent.qlearner.save(&path).unwrap();
+        let q = Q::<3>::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // X threatens the top row, so O must block
+        let board: Board<3> = "XX./.O./... o".parse().unwrap();
+        let analysis = analyze(&q, &board, &RewardScheme::classic(), &mut Solver::new(0)).unwrap();
+        assert_eq!(6, analysis.moves.len());
+        assert_eq!(Some((0, 2)), analysis.chosen);
+        assert!(analysis.moves.iter().all(|m| m.visits > 0));
+        let block = analysis.moves.iter().find(|m| m.action == (0, 2)).unwrap();
+        assert!(analysis.moves.iter().all(|m| m.q <= block.q));
+        assert_eq!(vec![(0, 2)], analysis.best_moves());
+    }
+
+    #[test]
+    fn test_value_map() {
+        let board: Board<3> = "XX./OO./X.. o".parse().unwrap();
+        let values = [
+            ((0, 2), -10.0),
+            ((1, 2), 100.0),
+            ((2, 1), 40.0),
+            ((2, 2), 80.0),
+        ];
+        assert_eq!(vec![(1, 2)], best_valued(&values));
+
+        let plain = value_map(&board, &values, false);
+        assert_eq!(
+            "      0     1     2\n0     X     X -10.0\n1     O     O 100.0\n2     X  40.0  80.0\n",
+            plain
+        );
+        let coloured = value_map(&board, &values, true);
+        assert!(coloured.contains("\x1b[1;32m100.0\x1b[0m"));
+        assert!(coloured.contains("\x1b[31m-10.0\x1b[0m"));
+        assert!(coloured.contains("\x1b[33m 40.0\x1b[0m"));
+        assert!(coloured.contains("\x1b[32m 80.0\x1b[0m"));
+    }
+}
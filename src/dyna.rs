@@ -0,0 +1,96 @@
+use rand::Rng;
+use rustc_hash::FxHashMap;
+
+use crate::tic_tac_toe::Board;
+
+/// One observed step: taking `action` in `state` led to `next_state` and `reward`.
+/// `done` is true if the game ended there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition<const N: usize> {
+    pub state: Board<N>,
+    pub action: (usize, usize),
+    pub next_state: Board<N>,
+    pub reward: f64,
+    pub done: bool,
+}
+
+/// A learned model of the environment for Dyna-Q, remembering the most recent
+/// outcome of every state and action the agent has tried. Against a random
+/// opponent the real outcome varies, so the model only approximates it.
+pub struct Model<const N: usize> {
+    index: FxHashMap<(Board<N>, (usize, usize)), usize>,
+    transitions: Vec<Transition<N>>,
+}
+
+impl<const N: usize> Model<N> {
+    pub fn new() -> Self {
+        Model {
+            index: FxHashMap::default(),
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Remember `transition`, replacing what was known for its state and action
+    pub fn record(&mut self, transition: Transition<N>) {
+        let key = (transition.state, transition.action);
+        match self.index.get(&key) {
+            Some(&idx) => self.transitions[idx] = transition,
+            None => {
+                self.index.insert(key, self.transitions.len());
+                self.transitions.push(transition);
+            }
+        }
+    }
+
+    /// A uniformly random remembered transition, or None if nothing is known yet
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Transition<N>> {
+        if self.transitions.is_empty() {
+            return None;
+        }
+        Some(self.transitions[rng.gen_range(0..self.transitions.len())])
+    }
+
+    /// How many state and action pairs are known
+    pub fn n_transitions(&self) -> usize {
+        self.transitions.len()
+    }
+}
+
+impl<const N: usize> Default for Model<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tic_tac_toe::Piece;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_record_replaces() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut model = Model::<3>::new();
+        assert_eq!(None, model.sample(&mut rng));
+
+        let state = Board::<3>::new();
+        let mut next_state = state;
+        next_state.board[0][0] = Piece::X;
+        let mut transition = Transition {
+            state,
+            action: (0, 0),
+            next_state,
+            reward: 0.0,
+            done: false,
+        };
+        model.record(transition);
+        transition.reward = 100.0;
+        transition.done = true;
+        model.record(transition);
+
+        assert_eq!(1, model.n_transitions());
+        assert_eq!(Some(transition), model.sample(&mut rng));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::error::{Error, Result};
+use crate::opponent::Opponent;
+use crate::reward::RewardScheme;
+use crate::tic_tac_toe::{Board, GameResult, Piece, Player};
+
+/// What the learner sees before each of its moves
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation<const N: usize> {
+    pub board: Board<N>,
+    /// Whether each action, numbered `row * N + col`, is an empty spot
+    pub legal_mask: Vec<bool>,
+}
+
+impl<const N: usize> Observation<N> {
+    fn new(board: Board<N>) -> Self {
+        let legal_mask = board
+            .board
+            .iter()
+            .flatten()
+            .map(|&p| p == Piece::Empty)
+            .collect();
+        Observation { board, legal_mask }
+    }
+
+    /// The board as one number per cell, row by row: 1 for X, -1 for O, 0 for empty
+    pub fn cells(&self) -> Vec<i8> {
+        self.board
+            .board
+            .iter()
+            .flatten()
+            .map(|&p| p as i8)
+            .collect()
+    }
+}
+
+/// Everything else `step` has to say about what happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Info {
+    /// How the game ended, if it did
+    pub result: Option<GameResult>,
+    /// Where the opponent replied, if it got to move
+    pub opponent_action: Option<(usize, usize)>,
+    /// The action was an occupied spot. The board is unchanged and the game is over.
+    pub illegal: bool,
+}
+
+/// A reinforcement learning environment in the style of OpenAI Gym. The learner
+/// plays `player`, and `opponent` moves inside `reset` and `step`, so every
+/// observation is a board with the learner to move. Rewards are the same ones
+/// `Agent::learn_one_game_against` gives, from `rewards`.
+///
+/// ```
+/// use qtictactoe::RandomOpponent;
+/// use qtictactoe::TicTacToeEnv;
+///
+/// let mut env = TicTacToeEnv::<3>::new(Box::new(RandomOpponent::seeded(0)));
+/// let mut obs = env.reset().unwrap();
+/// loop {
+///     let action = obs.legal_mask.iter().position(|&legal| legal).unwrap();
+///     let (next, _reward, done, _info) = env.step(action).unwrap();
+///     if done {
+///         break;
+///     }
+///     obs = next;
+/// }
+/// ```
+pub struct TicTacToeEnv<const N: usize> {
+    pub opponent: Box<dyn Opponent<N> + Send>,
+    pub rewards: RewardScheme,
+    /// Which side the learner plays. X moves first.
+    pub player: Player,
+    /// The board every game starts from
+    pub start: Board<N>,
+    board: Board<N>,
+    done: bool,
+}
+
+impl<const N: usize> TicTacToeEnv<N> {
+    /// The learner plays X against `opponent`, with the classic rewards
+    pub fn new(opponent: Box<dyn Opponent<N> + Send>) -> Self {
+        TicTacToeEnv {
+            opponent,
+            rewards: RewardScheme::classic(),
+            player: Player::X,
+            start: Board::new(),
+            board: Board::new(),
+            done: true,
+        }
+    }
+
+    /// How many actions there are, legal or not. Action `a` is the spot at row
+    /// `a / N`, column `a % N`.
+    pub fn action_space(&self) -> usize {
+        N * N
+    }
+
+    /// Whether each action is legal right now
+    pub fn legal_mask(&self) -> Vec<bool> {
+        Observation::new(self.board).legal_mask
+    }
+
+    /// Start a new game from `start`. If the opponent is to move there, it has
+    /// already moved. An error if the game is over before the learner gets to
+    /// move, because `start` is finished or the opponent's move finished it.
+    pub fn reset(&mut self) -> Result<Observation<N>> {
+        self.board = self.start;
+        self.done = true;
+        if self.board.get_winner().is_some() || self.board.is_ended() {
+            return Err(Error::GameOver);
+        }
+        let to_move = self.board.player_to_move();
+        if to_move != self.player {
+            let action = self.opponent.choose_move(&self.board, to_move);
+            if self.board.make_move(to_move, action.0, action.1).is_some() {
+                return Err(Error::GameOver);
+            }
+        }
+        self.done = false;
+        Ok(Observation::new(self.board))
+    }
+
+    /// Play `action`, then let the opponent reply if the game is not over. Returns
+    /// the board the learner sees next, its reward, whether the game is over, and
+    /// what happened.
+    ///
+    /// An action outside `action_space` is an error, as is stepping once the game
+    /// is over. Playing in a taken spot ends the game with the illegal move reward.
+    pub fn step(&mut self, action: usize) -> Result<(Observation<N>, f64, bool, Info)> {
+        if self.done {
+            return Err(Error::GameOver);
+        }
+        let (row, col) = (action / N, action % N);
+        if action >= N * N {
+            return Err(Error::IllegalMove { row, col });
+        }
+        let mut info = Info {
+            result: None,
+            opponent_action: None,
+            illegal: false,
+        };
+
+        if self.board.board[row][col] != Piece::Empty {
+            self.done = true;
+            info.illegal = true;
+            let reward = self.rewards.per_move + self.rewards.illegal_move;
+            return Ok((Observation::new(self.board), reward, true, info));
+        }
+
+        let mut result = self.board.make_move(self.player, row, col);
+        if result.is_none() {
+            let opponent = self.player.next_player();
+            let reply = self.opponent.choose_move(&self.board, opponent);
+            result = self.board.make_move(opponent, reply.0, reply.1);
+            info.opponent_action = Some(reply);
+        }
+
+        info.result = result;
+        self.done = result.is_some();
+        let reward = self.rewards.for_move(result, self.player);
+        Ok((Observation::new(self.board), reward, self.done, info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::RandomOpponent;
+
+    /// Always plays the first empty spot
+    fn first_empty(board: &Board<3>, _player: Player) -> (usize, usize) {
+        board.get_empty_spots()[0]
+    }
+
+    #[test]
+    fn test_step() {
+        let mut env = TicTacToeEnv::<3>::new(Box::new(first_empty));
+        let obs = env.reset().unwrap();
+        assert_eq!(vec![true; 9], obs.legal_mask);
+
+        // X takes the middle, O replies in the corner
+        let (obs, reward, done, info) = env.step(4).unwrap();
+        assert_eq!((0.0, false), (reward, done));
+        assert_eq!(Some((0, 0)), info.opponent_action);
+        assert_eq!(vec![-1, 0, 0, 0, 1, 0, 0, 0, 0], obs.cells());
+        assert!(!obs.legal_mask[0] && !obs.legal_mask[4]);
+
+        // Taking a used spot ends the game
+        let (_, reward, done, info) = env.step(0).unwrap();
+        assert_eq!((-100.0, true), (reward, done));
+        assert!(info.illegal);
+        assert!(matches!(env.step(1), Err(Error::GameOver)));
+    }
+
+    #[test]
+    fn test_win_reward() {
+        let mut env = TicTacToeEnv::<3>::new(Box::new(first_empty));
+        env.rewards = RewardScheme::unit();
+        env.reset().unwrap();
+        // O fills the top row from the left, X wins down the right hand side
+        env.step(2).unwrap();
+        env.step(5).unwrap();
+        let (_, reward, done, info) = env.step(8).unwrap();
+        assert_eq!((1.0, true), (reward, done));
+        assert_eq!(Some(GameResult::XWon), info.result);
+        assert!(matches!(env.step(9), Err(Error::GameOver)));
+    }
+
+    #[test]
+    fn test_play_as_o() {
+        let mut env = TicTacToeEnv::<3>::new(Box::new(RandomOpponent::seeded(0)));
+        env.player = Player::O;
+        let obs = env.reset().unwrap();
+        assert_eq!(8, obs.legal_mask.iter().filter(|&&legal| legal).count());
+        assert!(matches!(
+            env.step(env.action_space()),
+            Err(Error::IllegalMove { .. })
+        ));
+    }
+
+    #[test]
+    fn test_reset_follows_start() {
+        // O is to move, so the learner playing O moves first
+        let mut env = TicTacToeEnv::<3>::new(Box::new(first_empty));
+        env.player = Player::O;
+        env.start = "X../.../... o".parse().unwrap();
+        let obs = env.reset().unwrap();
+        assert_eq!(env.start, obs.board);
+
+        // X is to move, so the opponent moves for X first
+        env.player = Player::O;
+        env.start = "XO./.../... x".parse().unwrap();
+        let obs = env.reset().unwrap();
+        assert_eq!(vec![1, -1, 1, 0, 0, 0, 0, 0, 0], obs.cells());
+
+        // The opponent wins with its first move
+        env.start = "XX./OO./... x".parse().unwrap();
+        assert!(matches!(env.reset(), Err(Error::GameOver)));
+        assert!(matches!(env.step(5), Err(Error::GameOver)));
+    }
+}
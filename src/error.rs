@@ -0,0 +1,91 @@
+use std::fmt;
+use std::io;
+
+use crate::tic_tac_toe::Player;
+
+/// Everything that can go wrong when using this crate
+#[derive(Debug)]
+pub enum Error {
+    /// A move to a spot that is off the board or already taken
+    IllegalMove {
+        row: usize,
+        col: usize,
+    },
+    /// A move in a game that has already ended
+    GameOver,
+    /// A setting that could not be understood, e.g. a schedule or reward config
+    Config(String),
+    /// Position notation that could not be understood, or describes a position
+    /// that can not come up in a game
+    Notation(String),
+    /// A position that can not come up in a game
+    InvalidPosition(PositionError),
+    /// A line typed during a game that is not a move or a command
+    Input(String),
+    Io(io::Error),
+}
+
+/// Why a position can not come up in a game where X moves first and the players
+/// take turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    /// X must have as many pieces as O, or one more
+    PieceCount { x: usize, o: usize },
+    /// The game stops as soon as one side wins, so both can not have
+    BothWon,
+    /// `winner` has a line, but the other player moved last
+    WinnerDidNotMoveLast { winner: Player },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IllegalMove { row, col } => write!(f, "Can not move at {row},{col}"),
+            Error::GameOver => write!(f, "The game is already over"),
+            Error::Config(msg) | Error::Notation(msg) | Error::Input(msg) => write!(f, "{msg}"),
+            Error::InvalidPosition(err) => write!(f, "Impossible position: {err}"),
+            Error::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::PieceCount { x, o } => write!(
+                f,
+                "X has {x} pieces and O has {o}, but X moves first and they take turns"
+            ),
+            PositionError::BothWon => write!(f, "both players have won"),
+            PositionError::WinnerDidNotMoveLast { winner } => {
+                write!(f, "{winner:?} has won, but the other player moved last")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidPosition(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl From<PositionError> for Error {
+    fn from(err: PositionError) -> Self {
+        Error::InvalidPosition(err)
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+
+use crate::error::Result;
+use crate::opponent::{Outcome, PerfectPlay};
+use crate::q_matrix::QFunction;
+use crate::states;
+use crate::tic_tac_toe::{Board, Player};
+
+/// How many blunders `Exploitability`'s `Display` shows
+const SHOWN_BLUNDERS: usize = 5;
+
+/// A position where the learner's best move throws away a win or a draw
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blunder<const N: usize> {
+    pub board: Board<N>,
+    /// The move the learner plays
+    pub action: (usize, usize),
+    /// The outcome with perfect play from here
+    pub best: Outcome,
+    /// The moves that get `best`
+    pub best_moves: Vec<(usize, usize)>,
+}
+
+/// How the learner's best moves compare with perfect play, over every position
+/// X can reach and has to move in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exploitability<const N: usize> {
+    /// How many positions were checked
+    pub positions: usize,
+    /// Positions where `max_action_for_state` gives no move, so the learner would
+    /// move at random
+    pub unknown: usize,
+    /// Positions where the learner's move does worse than the best one
+    pub suboptimal: usize,
+    /// The suboptimal moves that lose, worst first: thrown away wins before thrown
+    /// away draws, then the ones closest to the start
+    pub blunders: Vec<Blunder<N>>,
+}
+
+impl<const N: usize> Exploitability<N> {
+    /// The fraction of positions where the learner's move is worse than perfect,
+    /// or where it has no move. A random move is not a learned one, so an
+    /// untrained learner scores 1.
+    pub fn suboptimal_rate(&self) -> f64 {
+        (self.suboptimal + self.unknown) as f64 / self.positions.max(1) as f64
+    }
+}
+
+/// Compare the moves `learner` rates best with perfect play from `oracle`, e.g. a
+/// `Solver` or a `Tablebase`, in every
+/// position reachable from `start` where X is to move. Agents learn as X, so this
+/// is every position an agent can be asked to move in. Every move by either side
+/// is followed, not just the learner's, so this only suits 3x3 boards. An error if
+/// `oracle` does not know how the moves in one of the positions turn out.
+pub fn exploitability<const N: usize>(
+    learner: &impl QFunction<N>,
+    start: Board<N>,
+    oracle: &mut impl PerfectPlay<N>,
+) -> Result<Exploitability<N>> {
+    let mut report = Exploitability {
+        positions: 0,
+        unknown: 0,
+        suboptimal: 0,
+        blunders: Vec::new(),
+    };
+    for board in states::decision_states(start) {
+        check_position(learner, board, oracle, &mut report)?;
+    }
+
+    report.blunders.sort_by_key(|b| {
+        let empty = b.board.get_empty_spots().len();
+        (std::cmp::Reverse(b.best), std::cmp::Reverse(empty))
+    });
+    Ok(report)
+}
+
+fn check_position<const N: usize>(
+    learner: &impl QFunction<N>,
+    board: Board<N>,
+    oracle: &mut impl PerfectPlay<N>,
+    report: &mut Exploitability<N>,
+) -> Result<()> {
+    report.positions += 1;
+    let action = match learner.max_action_for_state(board) {
+        (Some(action), _) => action,
+        (None, _) => {
+            report.unknown += 1;
+            return Ok(());
+        }
+    };
+
+    let outcomes: Vec<_> = oracle
+        .perfect_moves(&board, Player::X)?
+        .into_iter()
+        .map(|(action, entry)| (action, entry.outcome))
+        .collect();
+    let best = outcomes
+        .iter()
+        .map(|&(_, outcome)| outcome)
+        .max()
+        .expect("The game is not over");
+    let played = outcomes
+        .iter()
+        .find(|&&(a, _)| a == action)
+        .map_or(Outcome::Loss, |&(_, outcome)| outcome);
+
+    if played < best {
+        report.suboptimal += 1;
+        if played == Outcome::Loss {
+            report.blunders.push(Blunder {
+                board,
+                action,
+                best,
+                best_moves: outcomes
+                    .iter()
+                    .filter(|&&(_, outcome)| outcome == best)
+                    .map(|&(a, _)| a)
+                    .collect(),
+            });
+        }
+    }
+    Ok(())
+}
+
+impl<const N: usize> fmt::Display for Exploitability<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} positions, {} with no learned move, {} suboptimal moves, {:.1}% suboptimal or unlearned, {} blunders",
+            self.positions,
+            self.unknown,
+            self.suboptimal,
+            100.0 * self.suboptimal_rate(),
+            self.blunders.len()
+        )?;
+        for blunder in self.blunders.iter().take(SHOWN_BLUNDERS) {
+            writeln!(
+                f,
+                "\nPlays {:?}, which loses. {:?} would {}.",
+                blunder.action,
+                blunder.best_moves,
+                match blunder.best {
+                    Outcome::Win => "win",
+                    _ => "draw",
+                }
+            )?;
+            write!(f, "{}", blunder.board)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::Solver;
+    use crate::q_matrix::Q;
+
+    #[test]
+    fn test_empty_table() {
+        let report = exploitability(&Q::<3>::new(), Board::new(), &mut Solver::new(0)).unwrap();
+        assert_eq!(2_423, report.positions);
+        assert_eq!(report.positions, report.unknown);
+        assert_eq!(0, report.suboptimal);
+        assert_eq!(1.0, report.suboptimal_rate());
+    }
+
+    #[test]
+    fn test_finds_blunder() {
+        // X can win at (0, 2) but has learned to play (2, 2)
+        let board: Board<3> = "XX./OO./... x".parse().unwrap();
+        let mut q = Q::new();
+        q.values.entry(board).or_default().insert((2, 2), 1.0);
+
+        let report = exploitability(&q, board, &mut Solver::new(0)).unwrap();
+        assert_eq!(1, report.suboptimal);
+        assert_eq!(1, report.blunders.len());
+        assert_eq!(Outcome::Win, report.blunders[0].best);
+        assert_eq!(vec![(0, 2)], report.blunders[0].best_moves);
+    }
+
+    #[test]
+    fn test_trained_agent() {
+        let mut agent = crate::Agent::<3>::builder().seed(5).build();
+        let untrained = exploitability(&agent.qlearner, Board::new(), &mut Solver::new(0)).unwrap();
+        agent.learn(20_000);
+        let report = exploitability(&agent.qlearner, Board::new(), &mut Solver::new(0)).unwrap();
+        assert!(report.unknown < untrained.unknown);
+        assert!(report.suboptimal < report.positions - report.unknown);
+        assert!(report.suboptimal_rate() < untrained.suboptimal_rate());
+    }
+}
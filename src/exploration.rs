@@ -0,0 +1,260 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use rustc_hash::FxHashMap;
+
+use crate::q_matrix::QFunction;
+use crate::schedule::Schedule;
+use crate::tic_tac_toe::Board;
+
+/// How an `Agent` trades off trying new moves against playing the best known one
+pub trait ExplorationStrategy<const N: usize> {
+    /// Called before every training episode, counting from zero, of `total`
+    fn start_episode(&mut self, _episode: usize, _total: usize) {}
+
+    /// Pick one of `valid_actions` in `state`
+    fn choose(
+        &mut self,
+        qlearner: &dyn QFunction<N>,
+        state: Board<N>,
+        valid_actions: &[(usize, usize)],
+        rng: &mut dyn RngCore,
+    ) -> (usize, usize);
+}
+
+fn random_action(valid_actions: &[(usize, usize)], rng: &mut dyn RngCore) -> (usize, usize) {
+    *valid_actions
+        .choose(rng)
+        .expect("Nothing in valid_actions to select")
+}
+
+/// The valid action with the highest value, counting actions the learner has not
+/// seen at their default value
+fn greedy_action<const N: usize>(
+    qlearner: &dyn QFunction<N>,
+    state: Board<N>,
+    valid_actions: &[(usize, usize)],
+) -> (usize, usize) {
+    *valid_actions
+        .iter()
+        .max_by(|&&a, &&b| qlearner.get(state, a).total_cmp(&qlearner.get(state, b)))
+        .expect("Nothing in valid_actions to select")
+}
+
+/// With probability epsilon a random move, otherwise the best known move
+pub struct EpsilonGreedy {
+    pub schedule: Schedule,
+    pub eps: f64,
+}
+
+impl EpsilonGreedy {
+    pub fn new(schedule: Schedule) -> Self {
+        EpsilonGreedy {
+            eps: schedule.value(0, 1),
+            schedule,
+        }
+    }
+}
+
+impl<const N: usize> ExplorationStrategy<N> for EpsilonGreedy {
+    fn start_episode(&mut self, episode: usize, total: usize) {
+        self.eps = self.schedule.value(episode, total);
+    }
+
+    fn choose(
+        &mut self,
+        qlearner: &dyn QFunction<N>,
+        state: Board<N>,
+        valid_actions: &[(usize, usize)],
+        rng: &mut dyn RngCore,
+    ) -> (usize, usize) {
+        // If random draw from U(0, 1) < self.eps, return a random choice from valid_actions
+        if rng.gen_range(0.0..1.0) < self.eps {
+            return random_action(valid_actions, rng);
+        }
+
+        // Otherwise, get the best action for this state if any. If None, return random
+        // choice from valid_actions
+        match qlearner.max_action_for_state(state) {
+            (None, _) => random_action(valid_actions, rng),
+            (Some(action), _) => action,
+        }
+    }
+}
+
+/// Picks moves with probability proportional to `exp(Q / temperature)`, so high
+/// temperatures explore and low temperatures are nearly greedy
+pub struct Boltzmann {
+    pub schedule: Schedule,
+    pub temperature: f64,
+}
+
+impl Boltzmann {
+    pub fn new(schedule: Schedule) -> Self {
+        Boltzmann {
+            temperature: schedule.value(0, 1),
+            schedule,
+        }
+    }
+}
+
+impl<const N: usize> ExplorationStrategy<N> for Boltzmann {
+    fn start_episode(&mut self, episode: usize, total: usize) {
+        self.temperature = self.schedule.value(episode, total);
+    }
+
+    fn choose(
+        &mut self,
+        qlearner: &dyn QFunction<N>,
+        state: Board<N>,
+        valid_actions: &[(usize, usize)],
+        rng: &mut dyn RngCore,
+    ) -> (usize, usize) {
+        if self.temperature <= 0.0 {
+            return greedy_action(qlearner, state, valid_actions);
+        }
+        let values: Vec<f64> = valid_actions
+            .iter()
+            .map(|&a| qlearner.get(state, a) / self.temperature)
+            .collect();
+        // Subtract the max before exponentiating, so nothing overflows
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let weights = values.iter().map(|v| (v - max).exp());
+        let dist = WeightedIndex::new(weights).expect("The best action has weight 1");
+        valid_actions[dist.sample(rng)]
+    }
+}
+
+/// Upper confidence bounds: picks the move maximising
+/// `Q + c * sqrt(ln(visits to state) / visits to move)`, trying every move once first
+pub struct Ucb1<const N: usize> {
+    pub c: f64,
+    pub counts: FxHashMap<(Board<N>, (usize, usize)), f64>,
+    pub state_counts: FxHashMap<Board<N>, f64>,
+}
+
+impl<const N: usize> Ucb1<N> {
+    pub fn new(c: f64) -> Self {
+        Ucb1 {
+            c,
+            counts: FxHashMap::default(),
+            state_counts: FxHashMap::default(),
+        }
+    }
+}
+
+impl<const N: usize> ExplorationStrategy<N> for Ucb1<N> {
+    fn choose(
+        &mut self,
+        qlearner: &dyn QFunction<N>,
+        state: Board<N>,
+        valid_actions: &[(usize, usize)],
+        _rng: &mut dyn RngCore,
+    ) -> (usize, usize) {
+        let state_visits = self.state_counts.get(&state).copied().unwrap_or(0.0);
+        let bound = |action: (usize, usize)| match self.counts.get(&(state, action)) {
+            None => f64::INFINITY,
+            Some(visits) => {
+                qlearner.get(state, action) + self.c * (state_visits.ln() / visits).sqrt()
+            }
+        };
+        let action = *valid_actions
+            .iter()
+            .max_by(|&&a, &&b| bound(a).total_cmp(&bound(b)))
+            .expect("Nothing in valid_actions to select");
+
+        *self.state_counts.entry(state).or_insert(0.0) += 1.0;
+        *self.counts.entry((state, action)).or_insert(0.0) += 1.0;
+        action
+    }
+}
+
+/// Always greedy. Exploration comes from the learner starting every value
+/// optimistically high, e.g. with `Q::initial_value`, so untried moves look best
+/// until they have been tried.
+pub struct Optimistic;
+
+impl<const N: usize> ExplorationStrategy<N> for Optimistic {
+    fn choose(
+        &mut self,
+        qlearner: &dyn QFunction<N>,
+        state: Board<N>,
+        valid_actions: &[(usize, usize)],
+        _rng: &mut dyn RngCore,
+    ) -> (usize, usize) {
+        greedy_action(qlearner, state, valid_actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::q_matrix::Q;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    /// A Q table where (0, 0) is the best move from an empty board
+    fn q_with_best_move() -> Q<3> {
+        let mut q = Q::<3>::new();
+        let state = Board::<3>::new();
+        let mut next = state;
+        next.board[0][0] = crate::tic_tac_toe::Piece::X;
+        for _ in 0..5 {
+            q.update(state, (0, 0), next, 100.0);
+            q.update(state, (1, 1), next, -100.0);
+        }
+        q
+    }
+
+    #[test]
+    fn test_epsilon_greedy() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let q = q_with_best_move();
+        let state = Board::<3>::new();
+        let mut strategy = EpsilonGreedy::new(Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+        });
+        ExplorationStrategy::<3>::start_episode(&mut strategy, 100, 100);
+        assert_eq!(0.0, strategy.eps);
+        let action = strategy.choose(&q, state, &state.get_empty_spots(), &mut rng);
+        assert_eq!((0, 0), action);
+    }
+
+    #[test]
+    fn test_boltzmann_cold_is_greedy() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let q = q_with_best_move();
+        let state = Board::<3>::new();
+        let mut strategy = Boltzmann::new(Schedule::Constant(1.0));
+        for _ in 0..20 {
+            let action = strategy.choose(&q, state, &state.get_empty_spots(), &mut rng);
+            assert_eq!((0, 0), action);
+        }
+    }
+
+    #[test]
+    fn test_ucb1_tries_everything() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let q = q_with_best_move();
+        let state = Board::<3>::new();
+        let valid_actions = state.get_empty_spots();
+        let mut strategy = Ucb1::new(1.0);
+        let mut tried: Vec<_> = (0..9)
+            .map(|_| strategy.choose(&q, state, &valid_actions, &mut rng))
+            .collect();
+        tried.sort();
+        assert_eq!(valid_actions, tried);
+    }
+
+    #[test]
+    fn test_optimistic_prefers_untried() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut q = q_with_best_move();
+        q.initial_value = 1_000.0;
+        let state = Board::<3>::new();
+        let action = Optimistic.choose(&q, state, &state.get_empty_spots(), &mut rng);
+        assert_ne!((0, 0), action);
+        assert_ne!((1, 1), action);
+    }
+}
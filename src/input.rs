@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+
+/// A line typed by someone playing a game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Move at this row and column
+    Move(usize, usize),
+    Undo,
+    Hint,
+    Resign,
+    New,
+    Swap,
+    /// Save the game so far to this file
+    Save(PathBuf),
+    Quit,
+    Help,
+}
+
+impl Input {
+    /// What the commands do, for `help`
+    pub const HELP: &str = "\
+Moves: row then column, as 1,2 or 1 2, or column letter then row from the bottom, as b3
+undo       take back your last move and the reply to it
+hint       suggest a move
+resign     give up this game
+new        give up this game and start another
+swap       change sides
+save FILE  save the game so far
+quit       stop playing
+help       show this";
+
+    /// Read a command, or a move on an `N`x`N` board in any of the forms `Input::HELP`
+    /// lists. Commands can be in any case.
+    ///
+    /// ```
+    /// use qtictactoe::Input;
+    ///
+    /// assert_eq!(Input::Move(1, 2), Input::parse::<3>("1,2").unwrap());
+    /// assert_eq!(Input::Move(1, 2), Input::parse::<3>("1 2").unwrap());
+    /// // Column c, second row from the bottom
+    /// assert_eq!(Input::Move(1, 2), Input::parse::<3>("c2").unwrap());
+    /// assert!(Input::parse::<3>("3,0").is_err());
+    /// ```
+    pub fn parse<const N: usize>(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let input = match (word.to_ascii_lowercase().as_str(), rest) {
+            ("undo", "") => Input::Undo,
+            ("hint", "") => Input::Hint,
+            ("resign", "") => Input::Resign,
+            ("new", "") => Input::New,
+            ("swap", "") => Input::Swap,
+            ("quit" | "exit", "") => Input::Quit,
+            ("help" | "?", "") => Input::Help,
+            ("save", "") => return Err(Error::Input("Save to which file?".to_string())),
+            ("save", file) => Input::Save(PathBuf::from(file)),
+            _ => {
+                let (row, col) = parse_cell::<N>(line)?;
+                Input::Move(row, col)
+            }
+        };
+        Ok(input)
+    }
+}
+
+/// A cell given as `row,col`, `row col` or chess style
+fn parse_cell<const N: usize>(text: &str) -> Result<(usize, usize)> {
+    let numbers: Vec<&str> = text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let (row, col) = match numbers[..] {
+        [row, col] => match (row.parse(), col.parse()) {
+            (Ok(row), Ok(col)) => (row, col),
+            _ => return Err(not_understood(text)),
+        },
+        [cell] => parse_chess::<N>(cell).ok_or_else(|| not_understood(text))?,
+        _ => return Err(not_understood(text)),
+    };
+    if row >= N || col >= N {
+        return Err(Error::Input(format!("{text:?} is off the board")));
+    }
+    Ok((row, col))
+}
+
+/// A cell like `b3`: the column as a letter from `a` on the left, then the row
+/// counting from 1 at the bottom
+fn parse_chess<const N: usize>(cell: &str) -> Option<(usize, usize)> {
+    let mut chars = cell.chars();
+    let file = chars.next()?.to_ascii_lowercase();
+    let rank: usize = chars.as_str().parse().ok()?;
+    if !file.is_ascii_lowercase() {
+        return None;
+    }
+    let col = file as usize - 'a' as usize;
+    // Off the board, which `parse_cell` reports
+    if rank == 0 || rank > N {
+        return Some((N, col));
+    }
+    Some((N - rank, col))
+}
+
+fn not_understood(text: &str) -> Error {
+    Error::Input(format!(
+        "Did not understand {text:?}. Type help for the moves and commands"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moves() {
+        for text in ["1,2", " 1, 2 ", "1 2", "c2", "C2"] {
+            assert_eq!(
+                Input::Move(1, 2),
+                Input::parse::<3>(text).unwrap(),
+                "{text}"
+            );
+        }
+        assert_eq!(Input::Move(0, 0), Input::parse::<3>("a3").unwrap());
+        assert_eq!(Input::Move(3, 0), Input::parse::<4>("a1").unwrap());
+        for text in [
+            "", "3,0", "0 3", "d1", "a4", "a0", "1,2,0", "x,y", "1", "-1,0",
+        ] {
+            assert!(Input::parse::<3>(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(Input::Undo, Input::parse::<3>("undo").unwrap());
+        assert_eq!(Input::Quit, Input::parse::<3>("QUIT").unwrap());
+        assert_eq!(
+            Input::Save(PathBuf::from("my game.txt")),
+            Input::parse::<3>("save  my game.txt ").unwrap()
+        );
+        assert!(Input::parse::<3>("save").is_err());
+        assert!(Input::parse::<3>("undo 2").is_err());
+    }
+}
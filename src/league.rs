@@ -0,0 +1,178 @@
+use std::fmt;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::agent::{Agent, Record};
+use crate::opponent::FrozenQ;
+use crate::tic_tac_toe::Player;
+
+/// A frozen copy of the agent, and how the agent has done against it
+pub struct Snapshot<const N: usize> {
+    /// How many training games the agent had played when the copy was made
+    pub episode: usize,
+    pub player: FrozenQ<N>,
+    /// The agent's results against this snapshot while training
+    pub record: Record,
+}
+
+/// Trains an agent against a growing pool of frozen copies of itself. Every
+/// `snapshot_every` games the agent's table is copied into the pool, and every
+/// game is played against a snapshot picked with weight `(1 - score) ^ power`,
+/// where `score` is how well the agent has done against it. Snapshots the agent
+/// still loses to are picked most.
+pub struct League<const N: usize> {
+    pub snapshot_every: usize,
+    pub power: f64,
+    pub snapshots: Vec<Snapshot<N>>,
+    rng: StdRng,
+}
+
+/// How the finished agent does against each snapshot
+pub struct LeagueReport {
+    /// For every snapshot: when it was made, the agent's results against it during
+    /// training, and the results of the final agent playing its best moves
+    pub rows: Vec<(usize, Record, Record)>,
+}
+
+impl<const N: usize> League<N> {
+    pub fn new(snapshot_every: usize, seed: u64) -> Self {
+        League {
+            snapshot_every: snapshot_every.max(1),
+            power: 2.0,
+            snapshots: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn snapshot(&mut self, agent: &Agent<N>, episode: usize) {
+        self.snapshots.push(Snapshot {
+            episode,
+            player: FrozenQ {
+                rewards: agent.rewards,
+                ..FrozenQ::new(agent.qlearner.clone(), self.rng.r#gen())
+            },
+            record: Record::default(),
+        });
+    }
+
+    /// The snapshot to play next
+    fn pick(&mut self) -> usize {
+        let weights = self.snapshots.iter().map(|s| {
+            // Start every snapshot at one win and one loss, so new ones get played
+            let score = (s.record.score() * s.record.games() as f64 + 1.0)
+                / (s.record.games() as f64 + 2.0);
+            (1.0 - score).powf(self.power) + 1e-6
+        });
+        let dist = WeightedIndex::new(weights).expect("Every weight is positive");
+        dist.sample(&mut self.rng)
+    }
+
+    /// Train `agent` for `n` games, starting with a snapshot of it as it is now
+    pub fn train(&mut self, agent: &mut Agent<N>, n: usize) {
+        self.snapshot(agent, 0);
+        for episode in 0..n {
+            if episode > 0 && episode % self.snapshot_every == 0 {
+                self.snapshot(agent, episode);
+            }
+            let idx = self.pick();
+            let result = agent.learn_episode(episode, n, &mut self.snapshots[idx].player);
+            self.snapshots[idx].record.add(result, Player::X);
+        }
+    }
+
+    /// Play `games` games of the agent's best moves against every snapshot
+    pub fn report(&mut self, agent: &mut Agent<N>, games: usize) -> LeagueReport {
+        let rows = self
+            .snapshots
+            .iter_mut()
+            .map(|s| {
+                let evaluation = agent.record_against(games, &mut s.player);
+                (s.episode, s.record, evaluation)
+            })
+            .collect();
+        LeagueReport { rows }
+    }
+}
+
+impl LeagueReport {
+    /// Whether the final agent scores at least half the points against every
+    /// snapshot, i.e. loses to none of them overall
+    pub fn latest_dominates(&self) -> bool {
+        self.rows
+            .iter()
+            .all(|(_, _, evaluation)| evaluation.score() >= 0.5)
+    }
+}
+
+impl fmt::Display for LeagueReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>20} {:>20} {:>6}",
+            "snapshot", "training W/D/L", "final W/D/L", "score"
+        )?;
+        for (episode, training, evaluation) in &self.rows {
+            writeln!(
+                f,
+                "{:>10} {:>20} {:>20} {:>6.2}",
+                episode,
+                format!("{}/{}/{}", training.wins, training.draws, training.losses),
+                format!(
+                    "{}/{}/{}",
+                    evaluation.wins, evaluation.draws, evaluation.losses
+                ),
+                evaluation.score()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::Opponent;
+    use crate::tic_tac_toe::Board;
+
+    #[test]
+    fn test_league() {
+        let mut agent = Agent::<3>::builder().seed(3).build();
+        let mut league = League::new(1_000, 4);
+        league.train(&mut agent, 5_000);
+        assert_eq!(5, league.snapshots.len());
+
+        let played: usize = league.snapshots.iter().map(|s| s.record.games()).sum();
+        assert_eq!(5_000, played);
+
+        let report = league.report(&mut agent, 100);
+        assert!(report.latest_dominates(), "{report}");
+    }
+
+    #[test]
+    fn test_snapshots_play_o_for_real() {
+        let mut agent = Agent::<3>::builder().seed(3).build();
+        let mut league = League::new(5_000, 4);
+        league.train(&mut agent, 10_000);
+        let snapshot = &mut league.snapshots.last_mut().unwrap().player;
+
+        // X threatens the top row. Random play would block a fifth of the time.
+        let board: Board<3> = "XX./.O./... o".parse().unwrap();
+        for _ in 0..20 {
+            assert_eq!((0, 2), snapshot.choose_move(&board, Player::O));
+        }
+    }
+
+    #[test]
+    fn test_prefers_snapshots_it_loses_to() {
+        let agent = Agent::<3>::builder().seed(0).build();
+        let mut league = League::new(1, 0);
+        league.snapshot(&agent, 0);
+        league.snapshot(&agent, 1);
+        league.snapshots[0].record.wins = 50;
+        league.snapshots[1].record.losses = 50;
+        let picks: Vec<_> = (0..100).map(|_| league.pick()).collect();
+        assert!(picks.iter().filter(|&&idx| idx == 1).count() > 90);
+    }
+}
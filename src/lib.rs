@@ -0,0 +1,77 @@
+//! Learning to play tic-tac-toe, on boards from 3x3 up, with Q learning and
+//! friends.
+//!
+//! The game itself is [`Board`]. Learners implement [`QFunction`], and an
+//! [`Agent`] trains one by playing games against an [`Opponent`]:
+//!
+//! ```
+//! use qtictactoe::{Agent, Board, Opponent, Player, RandomOpponent, RewardScheme};
+//!
+//! let mut agent = Agent::<3>::builder()
+//!     .rewards(RewardScheme::unit())
+//!     .seed(7)
+//!     .build();
+//! agent.learn_against(2_000, &mut RandomOpponent::seeded(1));
+//!
+//! let win_rate = agent.win_rate(100, &mut RandomOpponent::seeded(2));
+//! assert!(win_rate > 0.5);
+//!
+//! // A trained agent is an opponent too
+//! let action = agent.choose_move(&Board::new(), Player::X);
+//! assert!(Board::<3>::new().get_empty_spots().contains(&action));
+//! ```
+
+mod agent;
+mod alphazero;
+mod analysis;
+mod dyna;
+mod env;
+mod error;
+mod exploitability;
+mod exploration;
+mod input;
+mod league;
+mod linear;
+mod mcts;
+mod mlp;
+mod notation;
+mod opponent;
+mod parallel;
+mod play;
+mod q_matrix;
+mod record;
+mod replay;
+mod reward;
+mod schedule;
+mod states;
+mod tablebase;
+mod tic_tac_toe;
+mod tournament;
+
+pub use agent::{Agent, AgentBuilder, Record};
+pub use alphazero::{AlphaZero, AlphaZeroConfig, GenerationReport};
+pub use analysis::{Analysis, MoveAnalysis, analyze, best_valued, value_map};
+pub use env::{Info, Observation, TicTacToeEnv};
+pub use error::{Error, PositionError, Result};
+pub use exploitability::{Blunder, Exploitability, exploitability};
+pub use exploration::{Boltzmann, EpsilonGreedy, ExplorationStrategy, Optimistic, Ucb1};
+pub use input::Input;
+pub use league::{League, LeagueReport, Snapshot};
+pub use linear::{FeatureExtractor, LineFeatures, LinearAgent, LinearValue};
+pub use mcts::{Budget, Mcts, Rollout};
+pub use mlp::{Mlp, MlpQ, Optimizer};
+pub use opponent::{
+    Entry, FrozenQ, GreedyOpponent, Minimax, Mixture, Opponent, OpponentSpec, Outcome, PerfectPlay,
+    RandomOpponent, Solver,
+};
+pub use parallel::ParallelTrainer;
+pub use play::{Hints, Next, play};
+pub use q_matrix::{Q, QFunction, StepSize};
+pub use record::{GameRecord, RecordedMove, record_size};
+pub use replay::{ReplayBuffer, Sampling};
+pub use reward::RewardScheme;
+pub use schedule::Schedule;
+pub use states::{StateCounts, canonical, map_cell, perft, reachable, unmap_cell};
+pub use tablebase::Tablebase;
+pub use tic_tac_toe::{Board, GameResult, Piece, Player};
+pub use tournament::{Entrant, Rating, Standings, round_robin};
@@ -0,0 +1,308 @@
+use crate::opponent::Opponent;
+use crate::reward::RewardScheme;
+use crate::tic_tac_toe::{Board, Piece, Player};
+
+use rand::SeedableRng;
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+/// Turns a board into a fixed length vector of features, as seen by `player`.
+/// This is what lets a `LinearValue` generalise between positions it has never seen.
+pub trait FeatureExtractor<const N: usize> {
+    /// Length of every vector returned by `features`
+    fn n_features(&self) -> usize;
+
+    fn features(&self, board: &Board<N>, player: Player) -> Vec<f64>;
+}
+
+/// Hand-crafted features, all scaled to lie in [0, 1]:
+/// - a bias term
+/// - for k in 1..=N, how many lines hold k of `player`'s pieces and none of the opponent's
+/// - the same counts for the opponent
+/// - centre occupancy for `player`, then for the opponent
+/// - corner occupancy for `player`, then for the opponent
+/// - whether it is `player`'s turn to move
+pub struct LineFeatures;
+
+impl LineFeatures {
+    /// The cells that make up the centre. One cell for odd N, four for even N.
+    fn centre_cells<const N: usize>() -> Vec<(usize, usize)> {
+        let mid = N / 2;
+        if N % 2 == 1 {
+            vec![(mid, mid)]
+        } else {
+            vec![
+                (mid - 1, mid - 1),
+                (mid - 1, mid),
+                (mid, mid - 1),
+                (mid, mid),
+            ]
+        }
+    }
+}
+
+impl<const N: usize> FeatureExtractor<N> for LineFeatures {
+    fn n_features(&self) -> usize {
+        1 + 2 * N + 2 + 2 + 1
+    }
+
+    fn features(&self, board: &Board<N>, player: Player) -> Vec<f64> {
+        let own = player.piece();
+        let opp = player.next_player().piece();
+
+        // Every row, column and both diagonals
+        let mut lines: Vec<Vec<Piece>> = Vec::with_capacity(2 * N + 2);
+        for idx in 0..N {
+            lines.push(board.board[idx].to_vec());
+            lines.push(board.get_col(idx).collect());
+        }
+        lines.push(board.get_lr_diag().collect());
+        lines.push(board.get_rl_diag().collect());
+        let n_lines = lines.len() as f64;
+
+        // Index k - 1 holds the number of open lines with k pieces
+        let mut own_lines = vec![0.0; N];
+        let mut opp_lines = vec![0.0; N];
+        for line in &lines {
+            let n_own = line.iter().filter(|&&p| p == own).count();
+            let n_opp = line.iter().filter(|&&p| p == opp).count();
+            if n_own > 0 && n_opp == 0 {
+                own_lines[n_own - 1] += 1.0 / n_lines;
+            } else if n_opp > 0 && n_own == 0 {
+                opp_lines[n_opp - 1] += 1.0 / n_lines;
+            }
+        }
+
+        let occupancy = |cells: &[(usize, usize)], piece: Piece| {
+            cells
+                .iter()
+                .filter(|&&(r, c)| board.board[r][c] == piece)
+                .count() as f64
+                / cells.len() as f64
+        };
+        let centre = Self::centre_cells::<N>();
+        let corners = [(0, 0), (0, N - 1), (N - 1, 0), (N - 1, N - 1)];
+
+        let mut features = Vec::with_capacity(<Self as FeatureExtractor<N>>::n_features(self));
+        features.push(1.0);
+        features.extend(own_lines);
+        features.extend(opp_lines);
+        features.push(occupancy(&centre, own));
+        features.push(occupancy(&centre, opp));
+        features.push(occupancy(&corners, own));
+        features.push(occupancy(&corners, opp));
+        features.push(if board.player_to_move() == player {
+            1.0
+        } else {
+            0.0
+        });
+        features
+    }
+}
+
+/// A linear model of the value of a board, i.e. `weights . features(board)`.
+/// Unlike `Q`, the memory used does not grow with the number of states visited.
+pub struct LinearValue<const N: usize, F: FeatureExtractor<N>> {
+    pub alpha: f64,
+    pub discount: f64,
+    pub weights: Vec<f64>,
+    pub extractor: F,
+}
+
+impl<const N: usize, F: FeatureExtractor<N>> LinearValue<N, F> {
+    pub fn new(extractor: F) -> Self {
+        LinearValue {
+            alpha: 0.05,
+            discount: 0.9,
+            weights: vec![0.0; extractor.n_features()],
+            extractor,
+        }
+    }
+
+    /// The estimated value of `board` for `player`
+    pub fn value(&self, board: &Board<N>, player: Player) -> f64 {
+        self.extractor
+            .features(board, player)
+            .iter()
+            .zip(&self.weights)
+            .map(|(f, w)| f * w)
+            .sum()
+    }
+
+    /// Semi-gradient TD(0) update, moving the value of `state` towards
+    /// `reward + discount * value(next_state)`. `next_state` is None when the game
+    /// ended, in which case the target is just `reward`.
+    pub fn update(
+        &mut self,
+        state: &Board<N>,
+        next_state: Option<&Board<N>>,
+        player: Player,
+        reward: f64,
+    ) {
+        let features = self.extractor.features(state, player);
+        let value: f64 = features.iter().zip(&self.weights).map(|(f, w)| f * w).sum();
+        let next_value = match next_state {
+            None => 0.0,
+            Some(next) => self.value(next, player),
+        };
+
+        let td_error = reward + self.discount * next_value - value;
+        for (w, f) in self.weights.iter_mut().zip(features) {
+            *w += self.alpha * td_error * f;
+        }
+    }
+}
+
+/// Learns to play as X against a random opponent, by estimating the value of the
+/// board left after each of its own moves.
+pub struct LinearAgent<const N: usize, F: FeatureExtractor<N>> {
+    pub eps: f64,
+    pub value: LinearValue<N, F>,
+    pub rewards: RewardScheme,
+    /// Where the random moves made while exploring come from
+    pub rng: StdRng,
+}
+
+impl<const N: usize, F: FeatureExtractor<N>> LinearAgent<N, F> {
+    pub fn new(extractor: F) -> Self {
+        LinearAgent {
+            eps: 1.0,
+            value: LinearValue::new(extractor),
+            rewards: RewardScheme::symmetric(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// The move whose resulting board has the highest estimated value for `player`
+    pub fn best_action(&self, state: Board<N>, player: Player) -> (usize, usize) {
+        state
+            .get_empty_spots()
+            .into_iter()
+            .map(|action| {
+                let mut next = state;
+                next.make_move(player, action.0, action.1);
+                (action, self.value.value(&next, player))
+            })
+            .fold(
+                None,
+                |best: Option<((usize, usize), f64)>, item| match best {
+                    Some(b) if b.1 >= item.1 => Some(b),
+                    _ => Some(item),
+                },
+            )
+            .expect("No valid actions to pick from")
+            .0
+    }
+
+    pub fn get_action(&mut self, state: Board<N>, player: Player) -> (usize, usize) {
+        // If random draw from U(0, 1) < self.eps, return a random choice
+        let u = Uniform::from(0.0..1.0);
+        if u.sample(&mut self.rng) < self.eps {
+            return *state
+                .get_empty_spots()
+                .choose(&mut self.rng)
+                .expect("Nothing in valid_actions to select");
+        }
+        self.best_action(state, player)
+    }
+
+    /// Play one game as X against `opponent`, learning from every move
+    pub fn learn_one_game_against(&mut self, opponent: &mut dyn Opponent<N>) {
+        let mut game = Board::<N>::new();
+        let player = Player::X;
+
+        // The board left after the agent's previous move
+        let mut prev_state: Option<Board<N>> = None;
+        loop {
+            let action = self.get_action(game, player);
+            let winner = game.make_move(player, action.0, action.1);
+            if let Some(prev) = prev_state {
+                self.value
+                    .update(&prev, Some(&game), player, self.rewards.per_move);
+            }
+            prev_state = Some(game);
+
+            if let Some(res) = winner {
+                self.value.update(
+                    &game,
+                    None,
+                    player,
+                    self.rewards.for_move(Some(res), player),
+                );
+                break;
+            }
+
+            // Other player makes its move
+            let opp_action = opponent.choose_move(&game, player.next_player());
+            let winner = game.make_move(player.next_player(), opp_action.0, opp_action.1);
+            if let Some(res) = winner {
+                let prev = prev_state.expect("The agent has moved at least once");
+                self.value.update(
+                    &prev,
+                    None,
+                    player,
+                    self.rewards.for_move(Some(res), player),
+                );
+                break;
+            }
+        }
+    }
+
+    /// Learn over `n` games against `opponent`, exploring less as it goes
+    pub fn learn_against(&mut self, n: usize, opponent: &mut dyn Opponent<N>) {
+        let exploration_decrease = 1.0 / (n as f64);
+        for _ in 0..n {
+            self.learn_one_game_against(opponent);
+            self.eps -= exploration_decrease;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::RandomOpponent;
+
+    #[test]
+    fn test_features_len() {
+        let b = Board::<5>::new();
+        let f = LineFeatures;
+        assert_eq!(
+            FeatureExtractor::<5>::n_features(&f),
+            f.features(&b, Player::X).len()
+        );
+    }
+
+    #[test]
+    fn test_features_open_lines() {
+        let mut b = Board::<3>::new();
+        b.board[1][1] = Piece::X;
+        let f = LineFeatures.features(&b, Player::X);
+        // The centre is on 4 of the 8 lines
+        assert_eq!(0.5, f[1]);
+        // Own centre occupancy, and O is to move
+        assert_eq!(1.0, f[7]);
+        assert_eq!(0.0, f[11]);
+
+        let f = LineFeatures.features(&b, Player::O);
+        assert_eq!(0.5, f[4]);
+        assert_eq!(1.0, f[8]);
+        assert_eq!(1.0, f[11]);
+    }
+
+    #[test]
+    fn test_update_moves_towards_reward() {
+        let mut v = LinearValue::<3, _>::new(LineFeatures);
+        let b = Board::<3>::new();
+        v.update(&b, None, Player::X, 1.0);
+        assert!(v.value(&b, Player::X) > 0.0);
+    }
+
+    #[test]
+    fn test_learn() {
+        let mut agent = LinearAgent::<4, _>::new(LineFeatures);
+        agent.rng = StdRng::seed_from_u64(0);
+        agent.learn_against(200, &mut RandomOpponent::seeded(1));
+    }
+}
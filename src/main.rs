@@ -1,131 +1,1117 @@
 use std::io;
-use std::str::FromStr;
 
-use clap::Parser;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use std::path::{Path, PathBuf};
 
-mod agent;
-mod q_matrix;
-mod tic_tac_toe;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-fn get_user_input() -> (usize, usize) {
-    println!("Enter row then column. E.g. 1,0");
-    let mut raw_input = String::new();
-    loop {
-        match io::stdin().read_line(&mut raw_input) {
-            Ok(_) => break,
-            Err(_) => continue,
+use qtictactoe::{
+    Agent, AgentBuilder, AlphaZero, AlphaZeroConfig, Board, Boltzmann, Budget, Entrant,
+    EpsilonGreedy, FrozenQ, GameRecord, GameResult, GreedyOpponent, Hints, League, LineFeatures,
+    LinearAgent, Mcts, Minimax, Mixture, Mlp, MlpQ, Next, Opponent, OpponentSpec, Optimistic,
+    Optimizer, ParallelTrainer, Q, QFunction, RandomOpponent, ReplayBuffer, RewardScheme, Rollout,
+    Sampling, Schedule, Solver, StateCounts, StepSize, Tablebase, Ucb1, analyze, exploitability,
+    perft, play, record_size, round_robin,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum HintStyle {
+    /// The values, as numbers on the board
+    Numbers,
+    /// The values, coloured from red for the worst to green for the best
+    Colour,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Learner {
+    /// A table of values for every state and action seen
+    Tabular,
+    /// A linear model over hand-crafted board features
+    Linear,
+    /// A neural network over a one-hot encoding of the board
+    Mlp,
+    /// Tree search guided by a policy and value network, trained by self-play.
+    /// The iterations are the total number of self-play games
+    AlphaZero,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OpponentKind {
+    /// The agent that was trained
+    Agent,
+    /// Monte Carlo Tree Search
+    Mcts,
+    /// Uniformly random moves
+    Random,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RolloutKind {
+    /// Uniformly random moves
+    Random,
+    /// Win if possible, otherwise block, otherwise random
+    Heuristic,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExplorationKind {
+    /// A random move with probability epsilon, otherwise the best known move
+    EpsilonGreedy,
+    /// Moves weighted by exp(Q / temperature)
+    Boltzmann,
+    /// The move with the highest upper confidence bound on its value
+    Ucb1,
+    /// Always the best known move, with every value starting optimistically high
+    Optimistic,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StepSizeKind {
+    /// Always alpha
+    Constant,
+    /// 1 / visits(state, action) ^ omega
+    Visits,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SamplingKind {
+    /// Every stored move is equally likely to be replayed
+    Uniform,
+    /// Moves the agent was most wrong about are replayed more often
+    Prioritised,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OptimizerKind {
+    /// Plain stochastic gradient descent
+    Sgd,
+    /// Adam, with the usual defaults
+    Adam,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play every pair of players against each other, then rate them
+    Tournament(TournamentArgs),
+    /// Count every position that can come up in a game
+    States(StatesArgs),
+    /// Solve every position and save the results, for `tablebase:FILE` opponents
+    /// and --tablebase
+    Tablebase {
+        /// Where to save the table
+        file: PathBuf,
+    },
+    /// Step forwards and backwards through a game saved with --save-games
+    Replay {
+        /// The saved game, on a board of any size
+        file: PathBuf,
+    },
+    /// Show what a saved table thinks of every move in a position, next to
+    /// perfect play
+    Analyze(AnalyzeArgs),
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    /// The position, e.g. "X.O/.X./..O o". Pass --size too if it is not 3x3
+    #[arg(value_name = "POSITION")]
+    board: String,
+
+    /// A table saved with --save-table
+    #[arg(long)]
+    table: PathBuf,
+
+    /// Look up perfect play in this table, made with the tablebase subcommand,
+    /// instead of solving the position
+    #[arg(long)]
+    tablebase: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct StatesArgs {
+    /// Count positions that are turns or flips of each other once
+    #[arg(long)]
+    symmetry: bool,
+
+    /// Also count the move sequences up to this many moves long, perft style
+    #[arg(long)]
+    perft: Option<usize>,
+}
+
+#[derive(Args)]
+struct TournamentArgs {
+    /// The players, as for --train-against, e.g. table:agent.q, mcts:2000 or
+    /// minimax:3. Give at least two
+    #[arg(required = true, num_args = 2..)]
+    players: Vec<OpponentSpec>,
+
+    /// Games between every pair of players. Who moves first alternates
+    #[arg(long, default_value_t = 100)]
+    games: usize,
+
+    /// Also write the ratings to this CSV file
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Also write the ratings and crosstable to this JSON file
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// How many games to train for. Defaults to 1000000, or for the alpha-zero
+    /// learner, whose games are far slower, 50 per generation
+    #[arg(value_parser = clap::value_parser!(usize))]
+    n_iters: Option<usize>,
+
+    /// Which kind of learner to train
+    #[arg(long, value_enum, default_value_t = Learner::Tabular)]
+    learner: Learner,
+
+    /// The number of rows (and columns) on the board
+    #[arg(long, global = true, value_parser = clap::value_parser!(u8).range(3..=5))]
+    #[arg(default_value_t = 3)]
+    size: u8,
+
+    /// How many in a row are needed to win. Defaults to the board size, and only
+    /// the alpha-zero learner can train for other values
+    #[arg(long, global = true)]
+    win_length: Option<usize>,
+
+    /// Start games from this position instead of an empty board, e.g.
+    /// "X.O/.X./..O x". Rows go top to bottom, then comes the side to move, then
+    /// the win length as k3 if it is not the board size
+    #[arg(long, global = true, conflicts_with = "win_length")]
+    position: Option<String>,
+
+    /// The tabular learner's learning rate, as a schedule like --explore-schedule
+    #[arg(long, default_value = "0.5")]
+    alpha: Schedule,
+
+    /// The tabular learner's discount on future rewards, as a schedule like
+    /// --explore-schedule
+    #[arg(long, default_value = "0.5")]
+    discount: Schedule,
+
+    /// How far the tabular learner moves each value towards its target
+    #[arg(long, value_enum, default_value_t = StepSizeKind::Constant)]
+    step_size: StepSizeKind,
+
+    /// The exponent for visit count step sizes. Between 0.5 and 1 for convergence
+    #[arg(long, default_value_t = 0.8)]
+    omega: f64,
+
+    /// How the tabular and mlp learners explore while they learn
+    #[arg(long, value_enum, default_value_t = ExplorationKind::EpsilonGreedy)]
+    exploration: ExplorationKind,
+
+    /// How epsilon (for epsilon-greedy) or the temperature (for boltzmann) changes
+    /// over training. One of constant:V, linear:START,END, exponential:START,DECAY or
+    /// step:START,FACTOR,EVERY
+    #[arg(long, default_value = "linear:1,0")]
+    explore_schedule: Schedule,
+
+    /// How much ucb1 exploration favours rarely tried moves
+    #[arg(long, default_value_t = 10.0)]
+    ucb_c: f64,
+
+    /// The value every move starts with, for optimistic exploration
+    #[arg(long, default_value_t = 100.0)]
+    optimistic_value: f64,
+
+    /// The rewards the tabular, linear and mlp learners get, and analyze values O's
+    /// moves with: classic (win 100, loss -100, draw 0), unit (win 1, draw 0.5,
+    /// loss 0) or symmetric (win 1, draw 0, loss -1). Defaults to classic, or
+    /// symmetric for the linear learner
+    #[arg(long, global = true, value_parser = ["classic", "unit", "symmetric"])]
+    rewards: Option<String>,
+
+    /// A file of `key = value` lines changing the rewards, after the preset. Keys
+    /// are preset, win, loss, draw, per_move and illegal_move
+    #[arg(long, global = true)]
+    reward_config: Option<PathBuf>,
+
+    /// Reward for winning, overriding the preset and config file
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    win_reward: Option<f64>,
+
+    /// Reward for losing, overriding the preset and config file
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    loss_reward: Option<f64>,
+
+    /// Reward for a draw, overriding the preset and config file
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    draw_reward: Option<f64>,
+
+    /// Reward for every move, overriding the preset and config file
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    move_reward: Option<f64>,
+
+    /// Dyna-Q planning updates made after every real move, by the tabular and mlp
+    /// learners
+    #[arg(long, default_value_t = 0)]
+    planning_steps: usize,
+
+    /// Stop training the tabular and mlp learners early, once they win this
+    /// fraction of their games against the training opponent
+    #[arg(long)]
+    target_win_rate: Option<f64>,
+
+    /// Store this many of the most recent moves, and replay them to the tabular
+    /// and mlp learners. Replay is off unless this is set
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    replay_capacity: Option<u64>,
+
+    /// How many stored moves are replayed after every real move
+    #[arg(long, default_value_t = 32)]
+    replay_batch: usize,
+
+    /// How stored moves are picked for replay
+    #[arg(long, value_enum, default_value_t = SamplingKind::Uniform)]
+    replay_sampling: SamplingKind,
+
+    /// Seed for picking the moves to replay. Defaults to one drawn from --seed
+    #[arg(long)]
+    replay_seed: Option<u64>,
+
+    /// Who the agent plays against while it learns. One of random, greedy (win or
+    /// block, otherwise random), minimax:DEPTH, solver (perfect play), mcts or
+    /// frozen:GAMES (a tabular agent trained for GAMES games, then frozen). Mix them
+    /// with weights, like 0.7*random+0.3*solver
+    #[arg(long, default_value = "random")]
+    train_against: OpponentSpec,
+
+    /// Who you play against once training is done
+    #[arg(long, value_enum, default_value_t = OpponentKind::Agent)]
+    play_against: OpponentKind,
+
+    /// Save every game you play to a file in this directory, to look back over
+    /// with the replay subcommand
+    #[arg(long)]
+    save_games: Option<PathBuf>,
+
+    /// Before each of your moves, show what the agent thinks every empty cell is
+    /// worth to you. Type hint instead of a move for its suggestion either way
+    #[arg(long, value_enum)]
+    hints: Option<HintStyle>,
+
+    /// How many iterations Monte Carlo Tree Search runs for each move
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 1000)]
+    mcts_iters: u64,
+
+    /// Give Monte Carlo Tree Search this many milliseconds per move, instead of a
+    /// number of iterations
+    #[arg(long)]
+    mcts_millis: Option<u64>,
+
+    /// How Monte Carlo Tree Search plays games out
+    #[arg(long, value_enum, default_value_t = RolloutKind::Random)]
+    mcts_rollout: RolloutKind,
+
+    /// How many generations the alpha-zero learner trains for
+    #[arg(long, default_value_t = 10)]
+    generations: usize,
+
+    /// How many search simulations the alpha-zero learner runs for each move
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 100)]
+    simulations: u64,
+
+    /// Width of the hidden layer of the mlp and alpha-zero learners
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 64)]
+    hidden: u64,
+
+    /// How the mlp learner's weights are updated
+    #[arg(long, value_enum, default_value_t = OptimizerKind::Adam)]
+    optimizer: OptimizerKind,
+
+    /// Step size of the mlp learner's optimizer
+    #[arg(long, default_value_t = 0.001)]
+    learning_rate: f64,
+
+    /// Start the mlp learner from weights saved in this file
+    #[arg(long)]
+    load_weights: Option<PathBuf>,
+
+    /// Start the tabular learner from a table saved with --save-table
+    #[arg(long)]
+    load_table: Option<PathBuf>,
+
+    /// Save the tabular learner's table to this file after training, e.g. to enter
+    /// it in a tournament
+    #[arg(long)]
+    save_table: Option<PathBuf>,
+
+    /// Save the mlp learner's weights to this file after training
+    #[arg(long)]
+    save_weights: Option<PathBuf>,
+
+    /// After training the tabular or mlp learner, check its best move in every
+    /// position against perfect play, and show the worst blunders. 3x3 boards only
+    #[arg(long)]
+    exploitability: bool,
+
+    /// Look up perfect play in this table, made with the tablebase subcommand,
+    /// instead of solving positions again
+    #[arg(long)]
+    tablebase: Option<PathBuf>,
+
+    /// Train the tabular learner against frozen copies of itself instead of
+    /// --train-against, adding a copy every this many games
+    #[arg(long)]
+    league_every: Option<usize>,
+
+    /// Train the tabular learner on this many threads, averaging their tables as
+    /// they go. 0 uses every available thread
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// With more than one thread, how many games each thread plays between
+    /// averaging their tables
+    #[arg(long, default_value_t = 1000)]
+    sync_every: usize,
+
+    /// Seed for every random choice, so that runs with the same seed and options
+    /// learn exactly the same thing. Random if not set
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+}
+
+impl Cli {
+    /// The rewards from the preset, then the config file, then single values
+    fn rewards(&self, default: RewardScheme) -> RewardScheme {
+        let mut rewards = match &self.rewards {
+            None => default,
+            Some(name) => RewardScheme::preset(name).expect("clap checks the name"),
+        };
+        if let Some(path) = &self.reward_config {
+            let text = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                Cli::command()
+                    .error(
+                        clap::error::ErrorKind::Io,
+                        format!("Could not read {}: {err}", path.display()),
+                    )
+                    .exit()
+            });
+            if let Err(err) = rewards.apply_config(&text) {
+                Cli::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!("In {}: {err}", path.display()),
+                    )
+                    .exit();
+            }
         }
+        rewards.win = self.win_reward.unwrap_or(rewards.win);
+        rewards.loss = self.loss_reward.unwrap_or(rewards.loss);
+        rewards.draw = self.draw_reward.unwrap_or(rewards.draw);
+        rewards.per_move = self.move_reward.unwrap_or(rewards.per_move);
+        println!("Rewards are {rewards}");
+        rewards
     }
-    let input = raw_input.trim().to_string();
 
-    // Parse the string
-    let pieces = input.split_once(',');
-    match pieces {
-        None => get_user_input(),
-        Some((r, c)) => {
-            let row_num: usize = match usize::from_str(r) {
-                Ok(val) => val,
-                Err(_) => return get_user_input(),
-            };
+    /// Exit if --win-length does not fit on the board
+    fn check_win_length<const N: usize>(&self) {
+        if self.win_length.is_some_and(|k| !(2..=N).contains(&k)) {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::ValueValidation,
+                    "--win-length must be between 2 and the board size",
+                )
+                .exit();
+        }
+    }
 
-            let col_num: usize = match usize::from_str(c) {
-                Ok(val) => val,
-                Err(_) => return get_user_input(),
-            };
+    /// The board every game starts from
+    fn start_board<const N: usize>(&self) -> Board<N> {
+        if let Some(position) = &self.position {
+            return position.parse().unwrap_or_else(|err| {
+                Cli::command()
+                    .error(clap::error::ErrorKind::ValueValidation, err)
+                    .exit()
+            });
+        }
+        match self.win_length {
+            None => Board::new(),
+            Some(k) => Board::with_win_length(k),
+        }
+    }
 
-            (row_num, col_num)
+    /// The generator every other one is seeded from
+    fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
         }
     }
-}
 
-fn play<const N: usize>(agent: &agent::Agent<N>) {
-    let mut game = tic_tac_toe::Board::<N>::new();
-    let mut player = tic_tac_toe::Player::X;
-    let mut action: (usize, usize);
-    let mut rng = thread_rng();
-    loop {
-        let (new_action, _) = agent.qlearner.max_action_for_state(game);
-        match new_action {
-            // The agent never learned this configuration
-            None => {
-                let valid_actions = game.get_empty_spots();
-                println!("Learner never came across this situation");
-                action = *valid_actions
-                    .choose(&mut rng)
-                    .expect("No valid states to randomly pick from");
+    fn mcts(&self, rng: &mut StdRng) -> Mcts {
+        let budget = match self.mcts_millis {
+            Some(ms) => Budget::Time(std::time::Duration::from_millis(ms)),
+            None => Budget::Iterations(self.mcts_iters as usize),
+        };
+        let mut mcts = Mcts::new(budget);
+        mcts.rollout = match self.mcts_rollout {
+            RolloutKind::Random => Rollout::Random,
+            RolloutKind::Heuristic => Rollout::Heuristic,
+        };
+        mcts.rng = StdRng::seed_from_u64(rng.r#gen());
+        mcts
+    }
+
+    /// How many games to train for, as given or the learner's default
+    fn n_iters(&self) -> usize {
+        self.n_iters.unwrap_or(match self.learner {
+            Learner::AlphaZero => {
+                self.generations * AlphaZeroConfig::default().games_per_generation
+            }
+            _ => 1_000_000,
+        })
+    }
+
+    fn training_opponent<const N: usize>(&self, rng: &mut StdRng) -> Box<dyn Opponent<N> + Send> {
+        self.opponent(&self.train_against, rng)
+    }
+
+    /// The opponent described by `spec`
+    fn opponent<const N: usize>(
+        &self,
+        spec: &OpponentSpec,
+        rng: &mut StdRng,
+    ) -> Box<dyn Opponent<N> + Send> {
+        match spec {
+            OpponentSpec::Random => Box::new(RandomOpponent::seeded(rng.r#gen())),
+            OpponentSpec::Greedy => Box::new(GreedyOpponent::seeded(rng.r#gen())),
+            OpponentSpec::Minimax(depth) => Box::new(Minimax::new(*depth, rng.r#gen())),
+            OpponentSpec::Solver => Box::new(Solver::new(rng.r#gen())),
+            OpponentSpec::Mcts(iters) => {
+                let mut mcts = self.mcts(rng);
+                if let Some(iters) = iters {
+                    mcts.budget = Budget::Iterations(*iters);
+                }
+                Box::new(mcts)
+            }
+            OpponentSpec::Table(path) => {
+                let q = load_or_exit(path, |p| Q::load(p));
+                Box::new(FrozenQ::new(q, rng.r#gen()))
+            }
+            OpponentSpec::Tablebase(path) => {
+                let mut table = load_tablebase(path, &self.start_board::<N>());
+                table.rng = StdRng::seed_from_u64(rng.r#gen());
+                Box::new(table)
             }
-            // The agent knows what to do
-            Some(act) => {
-                action = act;
+            OpponentSpec::Frozen(games) => {
+                let mut frozen = Agent::<N>::builder().seed(rng.r#gen()).build();
+                let mut random = RandomOpponent::seeded(rng.r#gen());
+                frozen.learn_episodes(0..*games, *games, &mut random);
+                Box::new(frozen.freeze())
+            }
+            OpponentSpec::Mixture(parts) => {
+                let opponents = parts
+                    .iter()
+                    .map(|(weight, spec)| (*weight, self.opponent(spec, rng)))
+                    .collect();
+                Box::new(Mixture::new(opponents, rng.r#gen()))
             }
         }
-        let winner = game.make_move(player, action.0, action.1);
-        println!("{}", game);
-        player = player.next_player();
-        if let Some(res) = winner {
-            match res {
-                tic_tac_toe::GameResult::XWon => {
-                    println!("=========== You Lost ===========");
-                    return;
-                }
-                tic_tac_toe::GameResult::OWon => {
-                    println!("=========== You Won ===========");
-                    return;
-                }
-                tic_tac_toe::GameResult::Tie => {
-                    println!("=========== Tie ===========");
-                    return;
-                }
+    }
+}
+
+/// Read a file with `load`, or exit with the reason it could not be read
+fn load_or_exit<T>(path: &Path, load: impl FnOnce(&Path) -> qtictactoe::Result<T>) -> T {
+    load(path).unwrap_or_else(|err| {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::Io,
+                format!("Could not load {}: {err}", path.display()),
+            )
+            .exit()
+    })
+}
+
+/// Load a tablebase and check it covers every position that can follow `start`,
+/// or exit
+fn load_tablebase<const N: usize>(path: &Path, start: &Board<N>) -> Tablebase<N> {
+    let table = load_or_exit(path, |p| Tablebase::load(p));
+    if let Err(err) = table.covers(start) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("Can not use {}: {err}", path.display()),
+            )
+            .exit();
+    }
+    table
+}
+
+/// Keep playing games against whoever `cli.play_against` asks for, until the
+/// human quits or the input runs out
+fn play_games<const N: usize>(cli: &Cli, agent: &mut impl Opponent<N>, rng: &mut StdRng) {
+    let mut mcts = cli.mcts(rng);
+    let mut random = RandomOpponent::seeded(rng.r#gen());
+    let hints = cli.hints.map(|style| match style {
+        HintStyle::Numbers => Hints::Numbers,
+        HintStyle::Colour => Hints::Colour,
+    });
+    let (mut input, mut output) = (io::stdin().lock(), io::stdout());
+    for game_num in 1.. {
+        println!("\nLet's play\n");
+        let (computer, name): (&mut dyn Opponent<N>, _) = match cli.play_against {
+            OpponentKind::Agent => (agent, "agent"),
+            OpponentKind::Mcts => (&mut mcts, "mcts"),
+            OpponentKind::Random => (&mut random, "random"),
+        };
+        let played = play(
+            cli.start_board::<N>(),
+            computer,
+            name,
+            hints,
+            &mut input,
+            &mut output,
+        );
+        let Ok((record, next)) = played else {
+            return;
+        };
+        if let Some(dir) = cli.save_games.as_ref().filter(|_| !record.moves.is_empty()) {
+            let seconds = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let path = dir.join(format!("game-{seconds}-{game_num}.txt"));
+            let saved = std::fs::create_dir_all(dir)
+                .map_err(qtictactoe::Error::from)
+                .and_then(|()| record.save(&path));
+            match saved {
+                Ok(()) => println!("Saved the game to {}", path.display()),
+                Err(err) => println!("Could not save the game: {err}"),
             }
         }
+        if next == Next::Quit {
+            return;
+        }
+    }
+}
 
-        let (x, y) = get_user_input();
-        let winner = game.make_move(player, x, y);
-        player = player.next_player();
-        println!("{}", game);
-        if let Some(res) = winner {
-            match res {
-                tic_tac_toe::GameResult::XWon => {
-                    println!("=========== You Lost ===========");
-                    return;
-                }
-                tic_tac_toe::GameResult::OWon => {
-                    println!("=========== You Won ===========");
-                    return;
-                }
-                tic_tac_toe::GameResult::Tie => {
-                    println!("=========== Tie ===========");
-                    return;
-                }
+/// Set up an `Agent` with the exploration, rewards, planning and replay asked for
+fn configure_agent<const N: usize, L: QFunction<N>>(
+    cli: &Cli,
+    builder: AgentBuilder<N, L>,
+    rewards: RewardScheme,
+    rng: &mut StdRng,
+) -> Agent<N, L> {
+    let mut builder = match cli.exploration {
+        ExplorationKind::EpsilonGreedy => {
+            builder.exploration(EpsilonGreedy::new(cli.explore_schedule))
+        }
+        ExplorationKind::Boltzmann => builder.exploration(Boltzmann::new(cli.explore_schedule)),
+        ExplorationKind::Ucb1 => builder.exploration(Ucb1::new(cli.ucb_c)),
+        ExplorationKind::Optimistic => builder.exploration(Optimistic),
+    }
+    .seed(rng.r#gen())
+    .rewards(rewards)
+    .planning_steps(cli.planning_steps);
+    if let Some(capacity) = cli.replay_capacity {
+        let sampling = match cli.replay_sampling {
+            SamplingKind::Uniform => Sampling::Uniform,
+            SamplingKind::Prioritised => Sampling::prioritised(),
+        };
+        let buffer = ReplayBuffer::new(
+            capacity as usize,
+            sampling,
+            cli.replay_seed.unwrap_or_else(|| rng.r#gen()),
+        );
+        builder = builder.replay(buffer, cli.replay_batch);
+    }
+    builder.build()
+}
+
+/// Train an `Agent`, either for every iteration or until it reaches the target
+/// win rate
+fn train_agent<const N: usize, L: QFunction<N>>(
+    cli: &Cli,
+    agent: &mut Agent<N, L>,
+    opponent: &mut dyn Opponent<N>,
+) {
+    match cli.target_win_rate {
+        None => agent.learn_against(cli.n_iters(), opponent),
+        Some(target) => {
+            let check_every = (cli.n_iters() / 100).max(1);
+            match agent.learn_until(target, cli.n_iters(), check_every, opponent) {
+                Some(episodes) => println!("Reached a win rate of {target} after {episodes} games"),
+                None => println!("Did not reach a win rate of {target}"),
             }
         }
     }
+    if let Some(replay) = &agent.replay {
+        println!("Replay buffer holds {} moves", replay.len());
+    }
+    if cli.planning_steps > 0 {
+        println!(
+            "Dyna-Q model knows {} transitions",
+            agent.model.n_transitions()
+        );
+    }
 }
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Cli {
-    /// How many iterations to train for
-    #[arg(value_parser = clap::value_parser!(usize))]
-    #[arg(default_value_t = 1000000)]
-    n_iters: usize,
+/// Print how often `learner`'s best moves fall short of perfect play, if asked to
+fn report_exploitability<const N: usize>(cli: &Cli, learner: &impl QFunction<N>, rng: &mut StdRng) {
+    if !cli.exploitability {
+        return;
+    }
+    let start = Board::new();
+    let report = match &cli.tablebase {
+        Some(path) => {
+            let mut table = load_tablebase(path, &start);
+            exploitability(learner, start, &mut table)
+        }
+        None => {
+            let mut solver = Solver::new(rng.r#gen());
+            exploitability(learner, start, &mut solver)
+        }
+    };
+    print!(
+        "{}",
+        report.expect("The table covers every position from the start")
+    );
 }
 
-fn main() {
-    let cli = Cli::parse();
+/// A tabular agent with the learning rate, discount and step size asked for
+fn tabular_agent<const N: usize>(cli: &Cli, rewards: RewardScheme, rng: &mut StdRng) -> Agent<N> {
+    let mut builder = match &cli.load_table {
+        None => Agent::<N>::builder(),
+        Some(path) => {
+            let q = load_or_exit(path, |p| Q::load(p));
+            AgentBuilder::new(q)
+        }
+    };
+    builder = builder
+        .alpha(cli.alpha)
+        .discount(cli.discount)
+        .step_size(match cli.step_size {
+            StepSizeKind::Constant => StepSize::Constant,
+            StepSizeKind::Visits => StepSize::Visits { omega: cli.omega },
+        });
+    if let ExplorationKind::Optimistic = cli.exploration {
+        builder = builder.initial_value(cli.optimistic_value);
+    }
+    configure_agent(cli, builder, rewards, rng)
+}
+
+/// Train a tabular agent against a league of snapshots of itself, then show how
+/// it does against each of them
+fn train_league<const N: usize>(
+    cli: &Cli,
+    every: usize,
+    rewards: RewardScheme,
+    rng: &mut StdRng,
+) -> Agent<N> {
+    if cli.threads != 1 || cli.target_win_rate.is_some() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--league-every can not be used with --threads or --target-win-rate",
+            )
+            .exit();
+    }
+    let mut q_agent = tabular_agent(cli, rewards, rng);
+    let mut league = League::new(every, rng.r#gen());
+    league.train(&mut q_agent, cli.n_iters());
+    println!(
+        "Have visited {} states at least once",
+        q_agent.qlearner.n_states()
+    );
 
-    let n_iters = cli.n_iters;
+    let report = league.report(&mut q_agent, 200);
+    print!("{report}");
+    if report.latest_dominates() {
+        println!("The final agent beats or draws with every snapshot");
+    } else {
+        println!("The final agent loses to at least one snapshot");
+    }
+    q_agent
+}
+
+/// Train a tabular agent on `threads` threads
+fn train_parallel<const N: usize>(
+    cli: &Cli,
+    threads: usize,
+    rewards: RewardScheme,
+    rng: &mut StdRng,
+) -> Agent<N> {
+    // Only Q tables are averaged between threads, so anything else an agent learns
+    // would be thrown away
+    let single_threaded = [
+        (cli.target_win_rate.is_some(), "--target-win-rate"),
+        (cli.planning_steps > 0, "--planning-steps"),
+        (cli.replay_capacity.is_some(), "--replay-capacity"),
+        (
+            matches!(cli.exploration, ExplorationKind::Ucb1),
+            "--exploration ucb1",
+        ),
+    ];
+    for (used, option) in single_threaded {
+        if used {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    format!("{option} can not be used with more than one thread"),
+                )
+                .exit();
+        }
+    }
+    println!("Training on {threads} threads");
+    let mut trainer = ParallelTrainer::new(threads, rng.r#gen());
+    trainer.sync_every = cli.sync_every;
+    let mut agent_rng = StdRng::seed_from_u64(rng.r#gen());
+    let q_agent = trainer.train(
+        cli.n_iters(),
+        || tabular_agent(cli, rewards, &mut agent_rng),
+        |seed| cli.training_opponent(&mut StdRng::seed_from_u64(seed)),
+    );
+    println!(
+        "Have visited {} states at least once",
+        q_agent.qlearner.n_states()
+    );
+    q_agent
+}
+
+fn run<const N: usize>(cli: &Cli) {
+    if cli.win_length.is_some() && !matches!(cli.learner, Learner::AlphaZero) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "Only the alpha-zero learner can use --win-length",
+            )
+            .exit();
+    }
+    if matches!(cli.exploration, ExplorationKind::Optimistic)
+        && !matches!(cli.learner, Learner::Tabular)
+    {
+        // Only a table has a starting value for moves it has never tried
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--exploration optimistic only works with the tabular learner",
+            )
+            .exit();
+    }
+    if matches!(cli.learner, Learner::Linear | Learner::AlphaZero) {
+        // These learners explore in their own way and learn from every move as it
+        // is played
+        let agent_only = [
+            (
+                !matches!(cli.exploration, ExplorationKind::EpsilonGreedy),
+                "--exploration",
+            ),
+            (
+                cli.explore_schedule
+                    != Schedule::Linear {
+                        start: 1.0,
+                        end: 0.0,
+                    },
+                "--explore-schedule",
+            ),
+            (cli.planning_steps > 0, "--planning-steps"),
+            (cli.target_win_rate.is_some(), "--target-win-rate"),
+            (cli.replay_capacity.is_some(), "--replay-capacity"),
+        ];
+        for (used, option) in agent_only {
+            if used {
+                Cli::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        format!("{option} only works with the tabular and mlp learners"),
+                    )
+                    .exit();
+            }
+        }
+    }
+    if cli.exploitability && N != 3 {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--exploitability only works on 3x3 boards",
+            )
+            .exit();
+    }
+    cli.check_win_length::<N>();
 
-    let mut q_agent = agent::Agent::<3>::new();
+    // Exit now, not after training, if --position is no good
+    cli.start_board::<N>();
+
+    let n_iters = cli.n_iters();
+    let mut rng = cli.rng();
     let start_time = std::time::Instant::now();
     println!("Learning for {n_iters} iterations");
-    q_agent.learn(n_iters);
-    println!("Learning took {:.2} s", start_time.elapsed().as_secs_f32());
 
+    match cli.learner {
+        Learner::Tabular => {
+            let rewards = cli.rewards(RewardScheme::classic());
+            let threads = match cli.threads {
+                0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+                n => n,
+            };
+            let q_agent = if let Some(every) = cli.league_every {
+                train_league(cli, every, rewards, &mut rng)
+            } else if threads > 1 {
+                train_parallel(cli, threads, rewards, &mut rng)
+            } else {
+                let mut training_opponent = cli.training_opponent::<N>(&mut rng);
+                let mut q_agent = tabular_agent(cli, rewards, &mut rng);
+                train_agent(cli, &mut q_agent, training_opponent.as_mut());
+                q_agent
+            };
+            println!("Learning took {:.2} s", start_time.elapsed().as_secs_f32());
+            if let Some(path) = &cli.save_table {
+                q_agent.qlearner.save(path).expect("Failed to save table");
+            }
+            report_exploitability(cli, &q_agent.qlearner, &mut rng);
+
+            play_games(cli, &mut q_agent.freeze(), &mut rng);
+        }
+        Learner::Linear => {
+            let mut training_opponent = cli.training_opponent::<N>(&mut rng);
+            let mut linear_agent = LinearAgent::<N, _>::new(LineFeatures);
+            linear_agent.rng = StdRng::seed_from_u64(rng.r#gen());
+            linear_agent.rewards = cli.rewards(RewardScheme::symmetric());
+            linear_agent.learn_against(n_iters, training_opponent.as_mut());
+            println!("Learning took {:.2} s", start_time.elapsed().as_secs_f32());
+
+            play_games(
+                cli,
+                &mut |game: &Board<N>, player| linear_agent.best_action(*game, player),
+                &mut rng,
+            );
+        }
+        Learner::Mlp => {
+            let mut training_opponent = cli.training_opponent::<N>(&mut rng);
+            let mut learner = match &cli.load_weights {
+                None => {
+                    MlpQ::<N>::new(cli.hidden as usize, &mut rng).expect("--hidden is at least 1")
+                }
+                Some(path) => load_or_exit(path, |p| MlpQ::<N>::from_network(Mlp::load(p)?)),
+            };
+            learner.network.learning_rate = cli.learning_rate;
+            learner.network.optimizer = match cli.optimizer {
+                OptimizerKind::Sgd => Optimizer::Sgd,
+                OptimizerKind::Adam => Optimizer::adam(),
+            };
+            let rewards = cli.rewards(RewardScheme::classic());
+            let builder = AgentBuilder::new(learner);
+            let mut mlp_agent = configure_agent(cli, builder, rewards, &mut rng);
+            train_agent(cli, &mut mlp_agent, training_opponent.as_mut());
+            println!("Learning took {:.2} s", start_time.elapsed().as_secs_f32());
+            if let Some(path) = &cli.save_weights {
+                mlp_agent
+                    .qlearner
+                    .network
+                    .save(path)
+                    .expect("Failed to save weights");
+            }
+            report_exploitability(cli, &mlp_agent.qlearner, &mut rng);
+
+            play_games(cli, &mut mlp_agent.freeze(), &mut rng);
+        }
+        Learner::AlphaZero => {
+            let config = AlphaZeroConfig {
+                generations: cli.generations,
+                games_per_generation: (n_iters / cli.generations.max(1)).max(1),
+                simulations: cli.simulations as usize,
+                ..AlphaZeroConfig::default()
+            };
+            let mut az =
+                AlphaZero::<N>::new(config, cli.hidden as usize, cli.start_board(), &mut rng)
+                    .expect("--simulations and --hidden are at least 1");
+            az.train(&mut rng, |report| {
+                println!(
+                    "Generation {}: {} positions, new network scored {:.2}{}",
+                    report.generation,
+                    report.examples,
+                    report.candidate_score,
+                    if report.promoted { ", promoted" } else { "" }
+                );
+            });
+            println!("Learning took {:.2} s", start_time.elapsed().as_secs_f32());
+
+            play_games(cli, &mut az, &mut rng);
+        }
+    }
+}
+
+/// Play a round robin between the players in `args`, and print the results
+fn run_tournament<const N: usize>(cli: &Cli, args: &TournamentArgs) {
+    let mut rng = cli.rng();
+    let mut entrants: Vec<_> = args
+        .players
+        .iter()
+        .map(|spec| Entrant {
+            name: spec.to_string(),
+            player: cli.opponent::<N>(spec, &mut rng),
+        })
+        .collect();
+    let standings = round_robin(&mut entrants, args.games, cli.start_board());
+    print!("{standings}");
+
+    for (path, text) in [
+        (&args.csv, standings.to_csv()),
+        (&args.json, standings.to_json()),
+    ] {
+        if let Some(path) = path {
+            std::fs::write(path, text).expect("Failed to write results");
+        }
+    }
+}
+
+/// Print how many positions can come up from the start board
+fn run_states<const N: usize>(cli: &Cli, args: &StatesArgs) {
+    cli.check_win_length::<N>();
+    let start = cli.start_board::<N>();
+    print!("{}", StateCounts::new(start, args.symmetry));
+    if let Some(depth) = args.perft {
+        for d in 1..=depth {
+            println!("perft({d}) = {}", perft(&start, d));
+        }
+    }
+}
+
+/// Solve every position from the start board and save the table to `file`
+fn build_tablebase<const N: usize>(cli: &Cli, file: &Path) {
+    cli.check_win_length::<N>();
+    let start_time = std::time::Instant::now();
+    let table = Tablebase::build(cli.start_board::<N>());
+    println!(
+        "Solved {} positions in {:.2} s",
+        table.len(),
+        start_time.elapsed().as_secs_f32()
+    );
+    table.save(file).expect("Failed to save tablebase");
+}
+
+/// Step through a saved game, reading commands from stdin
+fn replay_game<const N: usize>(file: &Path) {
+    let record: GameRecord<N> = load_or_exit(file, |p| GameRecord::load(p));
+    let positions = record.positions().expect("Loading checks every move");
+    println!(
+        "{} (X) against {} (O) on {}, {}",
+        record.x,
+        record.o,
+        record.date,
+        match record.result {
+            Some(GameResult::XWon) => "X won",
+            Some(GameResult::OWon) => "O won",
+            Some(GameResult::Tie) => "a tie",
+            None => "unfinished",
+        }
+    );
+    println!(
+        "Enter or n for the next move, b to go back, s for the start, e for the end, q to quit"
+    );
+
+    let mut idx = 0;
+    let mut lines = io::stdin().lines();
     loop {
-        println!("\nLet's play\n");
-        play(&q_agent);
+        if idx == 0 {
+            println!("\nStart");
+        } else {
+            let played = &record.moves[idx - 1];
+            let (row, col) = played.action;
+            print!("\n{}. {:?} plays {row},{col}", idx, record.mover(idx - 1));
+            match &played.annotation {
+                Some(note) => println!(" ({note})"),
+                None => println!(),
+            }
+        }
+        print!("{}", positions[idx]);
+
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+        match line.trim() {
+            "" | "n" => idx = (idx + 1).min(record.moves.len()),
+            "b" => idx = idx.saturating_sub(1),
+            "s" => idx = 0,
+            "e" => idx = record.moves.len(),
+            "q" => return,
+            other => println!("Unknown command {other:?}"),
+        }
+    }
+}
+
+/// Print every move in a position with its learned value and perfect play result
+fn run_analyze<const N: usize>(cli: &Cli, args: &AnalyzeArgs) {
+    let board: Board<N> = args.board.parse().unwrap_or_else(|err| {
+        Cli::command()
+            .error(clap::error::ErrorKind::ValueValidation, err)
+            .exit()
+    });
+    if board.get_winner().is_some() || board.is_ended() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                "The game is already over in that position",
+            )
+            .exit();
+    }
+    let q = load_or_exit(&args.table, |p| Q::load(p));
+    // O's moves that end the game are valued with the rewards the table learned
+    let rewards = cli.rewards(RewardScheme::classic());
+    let report = match &args.tablebase {
+        Some(path) => {
+            let mut table = load_tablebase(path, &board);
+            analyze(&q, &board, &rewards, &mut table)
+        }
+        None => {
+            let mut solver = Solver::new(cli.rng().r#gen());
+            analyze(&q, &board, &rewards, &mut solver)
+        }
+    };
+    print!(
+        "{}",
+        report.expect("The table covers every position from the board")
+    );
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    match (&cli.command, cli.size) {
+        (Some(Command::Tournament(args)), 3) => run_tournament::<3>(&cli, args),
+        (Some(Command::Tournament(args)), 4) => run_tournament::<4>(&cli, args),
+        (Some(Command::Tournament(args)), 5) => run_tournament::<5>(&cli, args),
+        (Some(Command::States(args)), 3) => run_states::<3>(&cli, args),
+        (Some(Command::States(args)), 4) => run_states::<4>(&cli, args),
+        (Some(Command::States(args)), 5) => run_states::<5>(&cli, args),
+        (Some(Command::Tablebase { file }), 3) => build_tablebase::<3>(&cli, file),
+        (Some(Command::Tablebase { file }), 4) => build_tablebase::<4>(&cli, file),
+        (Some(Command::Tablebase { file }), 5) => build_tablebase::<5>(&cli, file),
+        // The record says what size board it is on
+        (Some(Command::Replay { file }), _) => {
+            match load_or_exit(file, |p| record_size(&std::fs::read_to_string(p)?)) {
+                3 => replay_game::<3>(file),
+                4 => replay_game::<4>(file),
+                5 => replay_game::<5>(file),
+                size => Cli::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!(
+                            "{} is a {size}x{size} game, but only sizes 3 to 5 can be played",
+                            file.display()
+                        ),
+                    )
+                    .exit(),
+            }
+        }
+        (Some(Command::Analyze(args)), 3) => run_analyze::<3>(&cli, args),
+        (Some(Command::Analyze(args)), 4) => run_analyze::<4>(&cli, args),
+        (Some(Command::Analyze(args)), 5) => run_analyze::<5>(&cli, args),
+        (None, 3) => run::<3>(&cli),
+        (None, 4) => run::<4>(&cli),
+        (None, 5) => run::<5>(&cli),
+        _ => unreachable!("clap restricts the board size"),
     }
 }
@@ -0,0 +1,248 @@
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::opponent::Opponent;
+use crate::tic_tac_toe::{Board, GameResult, Player};
+
+/// How long to search for before picking a move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// At least one iteration is always run
+    Iterations(usize),
+    Time(Duration),
+}
+
+/// How moves are picked when playing a game out from a new node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rollout {
+    /// Uniformly random moves
+    Random,
+    /// Win if possible, otherwise block the opponent's win, otherwise random
+    Heuristic,
+}
+
+/// A node in the search tree. `score` is from the point of view of the player who
+/// made the move leading to this node.
+struct Node<const N: usize> {
+    board: Board<N>,
+    to_move: Player,
+    action: Option<(usize, usize)>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<(usize, usize)>,
+    result: Option<GameResult>,
+    visits: f64,
+    score: f64,
+}
+
+impl<const N: usize> Node<N> {
+    fn new(
+        board: Board<N>,
+        to_move: Player,
+        action: Option<(usize, usize)>,
+        parent: Option<usize>,
+        result: Option<GameResult>,
+    ) -> Self {
+        let untried = match result {
+            Some(_) => Vec::new(),
+            None => board.get_empty_spots(),
+        };
+        Node {
+            board,
+            to_move,
+            action,
+            parent,
+            children: Vec::new(),
+            untried,
+            result,
+            visits: 0.0,
+            score: 0.0,
+        }
+    }
+}
+
+/// Monte Carlo Tree Search with UCT selection. It needs no knowledge of the game
+/// beyond its rules, so works for any board size.
+pub struct Mcts {
+    pub budget: Budget,
+    /// The constant weighting exploration in UCT
+    pub exploration: f64,
+    pub rollout: Rollout,
+    /// Used when playing as an `Opponent`
+    pub rng: StdRng,
+}
+
+impl Mcts {
+    pub fn new(budget: Budget) -> Self {
+        Mcts {
+            budget,
+            exploration: std::f64::consts::SQRT_2,
+            rollout: Rollout::Random,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Search from `board` with `player` to move, and return the most visited move
+    pub fn search<const N: usize>(
+        &self,
+        board: &Board<N>,
+        player: Player,
+        rng: &mut impl Rng,
+    ) -> (usize, usize) {
+        let mut tree = vec![Node::new(*board, player, None, None, None)];
+        let start = Instant::now();
+        let mut iterations = 0;
+
+        while match self.budget {
+            Budget::Iterations(n) => iterations < n.max(1),
+            Budget::Time(limit) => iterations == 0 || start.elapsed() < limit,
+        } {
+            iterations += 1;
+
+            // Selection
+            let mut node = 0;
+            while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+                node = self.select_child(&tree, node);
+            }
+
+            // Expansion
+            if !tree[node].untried.is_empty() {
+                let idx = rng.gen_range(0..tree[node].untried.len());
+                let action = tree[node].untried.swap_remove(idx);
+                let mut next = tree[node].board;
+                let mover = tree[node].to_move;
+                let result = next.make_move(mover, action.0, action.1);
+                tree.push(Node::new(
+                    next,
+                    mover.next_player(),
+                    Some(action),
+                    Some(node),
+                    result,
+                ));
+                let child = tree.len() - 1;
+                tree[node].children.push(child);
+                node = child;
+            }
+
+            // Simulation
+            let result = match tree[node].result {
+                Some(res) => res,
+                None => self.play_out(tree[node].board, tree[node].to_move, rng),
+            };
+
+            // Backpropagation
+            let mut current = Some(node);
+            while let Some(idx) = current {
+                let n = &mut tree[idx];
+                n.visits += 1.0;
+                n.score += score_for(result, n.to_move.next_player());
+                current = n.parent;
+            }
+        }
+
+        let root = &tree[0];
+        let best = root
+            .children
+            .iter()
+            .max_by(|&&a, &&b| tree[a].visits.total_cmp(&tree[b].visits))
+            .expect("There were no moves to search");
+        tree[*best].action.expect("Only the root has no action")
+    }
+
+    /// The child of `node` with the highest upper confidence bound
+    fn select_child<const N: usize>(&self, tree: &[Node<N>], node: usize) -> usize {
+        let ln_visits = tree[node].visits.ln();
+        let uct = |child: usize| {
+            let c = &tree[child];
+            c.score / c.visits + self.exploration * (ln_visits / c.visits).sqrt()
+        };
+        *tree[node]
+            .children
+            .iter()
+            .max_by(|&&a, &&b| uct(a).total_cmp(&uct(b)))
+            .expect("Only called on nodes with children")
+    }
+
+    /// Play moves until the game ends, and return how it ended
+    fn play_out<const N: usize>(
+        &self,
+        mut board: Board<N>,
+        mut player: Player,
+        rng: &mut impl Rng,
+    ) -> GameResult {
+        loop {
+            let action = match self.rollout {
+                Rollout::Random => None,
+                Rollout::Heuristic => board
+                    .winning_move(player)
+                    .or_else(|| board.winning_move(player.next_player())),
+            };
+            let action = action.unwrap_or_else(|| {
+                *board
+                    .get_empty_spots()
+                    .choose(rng)
+                    .expect("Failed to notice that the game was over")
+            });
+            if let Some(res) = board.make_move(player, action.0, action.1) {
+                return res;
+            }
+            player = player.next_player();
+        }
+    }
+}
+
+/// 1 for a win for `player`, 0.5 for a tie and 0 for a loss
+fn score_for(result: GameResult, player: Player) -> f64 {
+    match (result, player) {
+        (GameResult::Tie, _) => 0.5,
+        (GameResult::XWon, Player::X) | (GameResult::OWon, Player::O) => 1.0,
+        _ => 0.0,
+    }
+}
+
+impl<const N: usize> Opponent<N> for Mcts {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        // `search` borrows all of self, so search with a copy of the rng
+        let mut rng = self.rng.clone();
+        let action = self.search(board, player, &mut rng);
+        self.rng = rng;
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_takes_win() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let b: Board<3> = "XX./OO./... x".parse().unwrap();
+        let mcts = Mcts::new(Budget::Iterations(1_000));
+        assert_eq!((0, 2), mcts.search(&b, Player::X, &mut rng));
+    }
+
+    #[test]
+    fn test_blocks_loss() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let b: Board<3> = "XX./.O./... o".parse().unwrap();
+        // X threatens (0, 2) along the top row
+        let mcts = Mcts::new(Budget::Iterations(2_000));
+        assert_eq!((0, 2), mcts.search(&b, Player::O, &mut rng));
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut mcts = Mcts::new(Budget::Time(Duration::from_millis(10)));
+        mcts.rollout = Rollout::Heuristic;
+        let b = Board::<4>::new();
+        let (row, col) = mcts.search(&b, Player::X, &mut rng);
+        assert!(row < 4 && col < 4);
+    }
+}
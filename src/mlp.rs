@@ -0,0 +1,460 @@
+use std::fs;
+use std::path::Path;
+
+use rand::Rng;
+use rand::distributions::{Distribution, Uniform};
+
+use crate::error::{Error, Result};
+use crate::q_matrix::QFunction;
+use crate::tic_tac_toe::{Board, Piece};
+
+/// How the weights are moved along the gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimizer {
+    Sgd,
+    Adam { beta1: f64, beta2: f64, eps: f64 },
+}
+
+impl Optimizer {
+    /// Adam with the usual defaults
+    pub fn adam() -> Self {
+        Optimizer::Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+        }
+    }
+}
+
+/// A fully connected layer. `weights` is stored row major, one row per output.
+#[derive(Debug, Clone)]
+struct Layer {
+    n_in: usize,
+    n_out: usize,
+    weights: Vec<f64>,
+    biases: Vec<f64>,
+    // Adam's running first and second moments, for the weights then the biases
+    m: Vec<f64>,
+    v: Vec<f64>,
+}
+
+impl Layer {
+    fn new(n_in: usize, n_out: usize, rng: &mut impl Rng) -> Self {
+        // Xavier/Glorot uniform initialisation
+        let limit = (6.0 / (n_in + n_out) as f64).sqrt();
+        let u = Uniform::from(-limit..limit);
+        let n_params = n_in * n_out + n_out;
+        Layer {
+            n_in,
+            n_out,
+            weights: (0..n_in * n_out).map(|_| u.sample(rng)).collect(),
+            biases: vec![0.0; n_out],
+            m: vec![0.0; n_params],
+            v: vec![0.0; n_params],
+        }
+    }
+
+    fn forward(&self, input: &[f64]) -> Vec<f64> {
+        self.weights
+            .chunks(self.n_in)
+            .zip(&self.biases)
+            .map(|(row, b)| row.iter().zip(input).map(|(w, x)| w * x).sum::<f64>() + b)
+            .collect()
+    }
+}
+
+/// A multilayer perceptron with tanh hidden layers and a linear output layer
+#[derive(Debug, Clone)]
+pub struct Mlp {
+    pub learning_rate: f64,
+    pub optimizer: Optimizer,
+    layers: Vec<Layer>,
+    // The number of optimisation steps taken, for Adam's bias correction
+    steps: i32,
+}
+
+impl Mlp {
+    /// `sizes` holds the width of every layer, starting with the input and ending
+    /// with the output, e.g. `[27, 64, 9]`. An error if there are fewer than two
+    /// sizes, or any is zero.
+    pub fn new(sizes: &[usize], rng: &mut impl Rng) -> Result<Self> {
+        check_sizes(sizes)?;
+        Ok(Mlp {
+            learning_rate: 0.001,
+            optimizer: Optimizer::adam(),
+            layers: sizes
+                .windows(2)
+                .map(|w| Layer::new(w[0], w[1], rng))
+                .collect(),
+            steps: 0,
+        })
+    }
+
+    pub fn forward(&self, input: &[f64]) -> Vec<f64> {
+        self.activations(input)
+            .pop()
+            .expect("There is always an output layer")
+    }
+
+    /// The output of every layer, starting with `input` itself
+    fn activations(&self, input: &[f64]) -> Vec<Vec<f64>> {
+        let mut activations = vec![input.to_vec()];
+        for (idx, layer) in self.layers.iter().enumerate() {
+            let mut out = layer.forward(activations.last().expect("Never empty"));
+            if idx + 1 < self.layers.len() {
+                out.iter_mut().for_each(|x| *x = x.tanh());
+            }
+            activations.push(out);
+        }
+        activations
+    }
+
+    /// Take one optimisation step, given the gradient of the loss with respect to
+    /// the network's output for `input`
+    pub fn backward(&mut self, input: &[f64], output_grad: &[f64]) {
+        let activations = self.activations(input);
+        self.steps += 1;
+
+        let mut grad = output_grad.to_vec();
+        for (idx, layer) in self.layers.iter_mut().enumerate().rev() {
+            let layer_input = &activations[idx];
+
+            // Gradient with respect to this layer's input, before it is updated
+            let mut input_grad = vec![0.0; layer.n_in];
+            for (out, g) in grad.iter().enumerate() {
+                let row = &layer.weights[out * layer.n_in..(out + 1) * layer.n_in];
+                for (ig, w) in input_grad.iter_mut().zip(row) {
+                    *ig += g * w;
+                }
+            }
+
+            let n_weights = layer.n_in * layer.n_out;
+            for (out, g) in grad.iter().enumerate() {
+                for (i, x) in layer_input.iter().enumerate() {
+                    let p = out * layer.n_in + i;
+                    let step = optimizer_step(
+                        self.optimizer,
+                        self.learning_rate,
+                        self.steps,
+                        &mut layer.m[p],
+                        &mut layer.v[p],
+                        g * x,
+                    );
+                    layer.weights[p] -= step;
+                }
+                let p = n_weights + out;
+                let step = optimizer_step(
+                    self.optimizer,
+                    self.learning_rate,
+                    self.steps,
+                    &mut layer.m[p],
+                    &mut layer.v[p],
+                    *g,
+                );
+                layer.biases[out] -= step;
+            }
+
+            // Back through the tanh of the previous layer
+            if idx > 0 {
+                grad = input_grad
+                    .iter()
+                    .zip(layer_input)
+                    .map(|(g, a)| g * (1.0 - a * a))
+                    .collect();
+            }
+        }
+    }
+
+    /// How many inputs the network takes
+    pub fn input_size(&self) -> usize {
+        self.layers[0].n_in
+    }
+
+    /// How many outputs the network gives
+    pub fn output_size(&self) -> usize {
+        self.layers[self.layers.len() - 1].n_out
+    }
+
+    /// Write the layer sizes, then every layer's weights and biases, as text
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut sizes = vec![self.layers[0].n_in];
+        sizes.extend(self.layers.iter().map(|l| l.n_out));
+
+        let mut out = join(&sizes);
+        out.push('\n');
+        for layer in &self.layers {
+            out.push_str(&join(&layer.weights));
+            out.push('\n');
+            out.push_str(&join(&layer.biases));
+            out.push('\n');
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Read a network written by `save`. The optimiser state is not saved, so it
+    /// starts afresh.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let sizes: Vec<usize> = parse_line(lines.next())?;
+        check_sizes(&sizes)?;
+
+        let mut layers = Vec::with_capacity(sizes.len() - 1);
+        for w in sizes.windows(2) {
+            let weights: Vec<f64> = parse_line(lines.next())?;
+            let biases: Vec<f64> = parse_line(lines.next())?;
+            if weights.len() != w[0] * w[1] || biases.len() != w[1] {
+                return Err(config_error("Layer does not match its size"));
+            }
+            let n_params = weights.len() + biases.len();
+            layers.push(Layer {
+                n_in: w[0],
+                n_out: w[1],
+                weights,
+                biases,
+                m: vec![0.0; n_params],
+                v: vec![0.0; n_params],
+            });
+        }
+
+        Ok(Mlp {
+            learning_rate: 0.001,
+            optimizer: Optimizer::adam(),
+            layers,
+            steps: 0,
+        })
+    }
+}
+
+/// How much to subtract from a parameter with gradient `grad`
+fn optimizer_step(
+    optimizer: Optimizer,
+    learning_rate: f64,
+    steps: i32,
+    m: &mut f64,
+    v: &mut f64,
+    grad: f64,
+) -> f64 {
+    match optimizer {
+        Optimizer::Sgd => learning_rate * grad,
+        Optimizer::Adam { beta1, beta2, eps } => {
+            *m = beta1 * *m + (1.0 - beta1) * grad;
+            *v = beta2 * *v + (1.0 - beta2) * grad * grad;
+            let m_hat = *m / (1.0 - beta1.powi(steps));
+            let v_hat = *v / (1.0 - beta2.powi(steps));
+            learning_rate * m_hat / (v_hat.sqrt() + eps)
+        }
+    }
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Every layer needs a width, and there must be an input and an output layer
+fn check_sizes(sizes: &[usize]) -> Result<()> {
+    if sizes.len() < 2 {
+        return Err(config_error("Need at least an input and output size"));
+    }
+    if sizes.contains(&0) {
+        return Err(config_error("Every layer needs at least one unit"));
+    }
+    Ok(())
+}
+
+fn parse_line<T: std::str::FromStr>(line: Option<&str>) -> Result<Vec<T>> {
+    line.ok_or_else(|| config_error("Unexpected end of file"))?
+        .split_whitespace()
+        .map(|x| {
+            x.parse()
+                .map_err(|_| config_error("Could not parse number"))
+        })
+        .collect()
+}
+
+fn config_error(msg: &str) -> Error {
+    Error::Config(msg.to_string())
+}
+
+/// One-hot encode a board, with three inputs per cell: empty, X and O
+pub fn one_hot<const N: usize>(board: &Board<N>) -> Vec<f64> {
+    let mut input = vec![0.0; 3 * N * N];
+    for (idx, piece) in board.board.iter().flatten().enumerate() {
+        let offset = match piece {
+            Piece::Empty => 0,
+            Piece::X => 1,
+            Piece::O => 2,
+        };
+        input[3 * idx + offset] = 1.0;
+    }
+    input
+}
+
+/// Approximates `Q` with a network that maps a one-hot board to one value per cell.
+/// It is trained towards the same target as `Q::update`.
+pub struct MlpQ<const N: usize> {
+    pub discount: f64,
+    /// The network predicts values divided by this, to keep its outputs near 1
+    pub value_scale: f64,
+    pub network: Mlp,
+}
+
+impl<const N: usize> MlpQ<N> {
+    /// An error if `hidden` is zero
+    pub fn new(hidden: usize, rng: &mut impl Rng) -> Result<Self> {
+        Self::from_network(Mlp::new(&[3 * N * N, hidden, N * N], rng)?)
+    }
+
+    /// Wrap an existing network, e.g. one made by `Mlp::load`. It must take the
+    /// `3 * N * N` inputs of `one_hot` and give one value per cell.
+    pub fn from_network(network: Mlp) -> Result<Self> {
+        let (inputs, outputs) = (network.input_size(), network.output_size());
+        if inputs != 3 * N * N || outputs != N * N {
+            return Err(Error::Config(format!(
+                "A network for a {N}x{N} board needs {} inputs and {} outputs, not {inputs} and {outputs}",
+                3 * N * N,
+                N * N
+            )));
+        }
+        Ok(MlpQ {
+            discount: 0.5,
+            value_scale: 100.0,
+            network,
+        })
+    }
+}
+
+impl<const N: usize> QFunction<N> for MlpQ<N> {
+    fn get(&self, state: Board<N>, action: (usize, usize)) -> f64 {
+        self.network.forward(&one_hot(&state))[action.0 * N + action.1] * self.value_scale
+    }
+
+    /// The best empty cell. Finished games have no actions, so are (None, 0.0).
+    fn max_action_for_state(&self, state: Board<N>) -> (Option<(usize, usize)>, f64) {
+        if state.get_winner().is_some() {
+            return (None, 0.0);
+        }
+        let values = self.network.forward(&one_hot(&state));
+        state
+            .get_empty_spots()
+            .into_iter()
+            .map(|(r, c)| ((r, c), values[r * N + c] * self.value_scale))
+            .fold((None, 0.0), |accum, item| match accum {
+                (Some(_), best) if best >= item.1 => accum,
+                _ => (Some(item.0), item.1),
+            })
+    }
+
+    fn update(
+        &mut self,
+        state: Board<N>,
+        action: (usize, usize),
+        next_state: Board<N>,
+        reward: f64,
+    ) {
+        let (_, next_q) = self.max_action_for_state(next_state);
+        let target = (reward + self.discount * next_q) / self.value_scale;
+
+        // Gradient of the squared error, only through the output for `action`
+        let input = one_hot(&state);
+        let idx = action.0 * N + action.1;
+        let mut output_grad = vec![0.0; N * N];
+        output_grad[idx] = self.network.forward(&input)[idx] - target;
+        self.network.backward(&input, &output_grad);
+    }
+
+    fn discount(&self) -> f64 {
+        self.discount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_one_hot() {
+        let mut b = Board::<3>::new();
+        b.board[0][1] = Piece::X;
+        b.board[2][2] = Piece::O;
+        let input = one_hot(&b);
+        assert_eq!(27, input.len());
+        assert_eq!(9.0, input.iter().sum::<f64>());
+        assert_eq!(1.0, input[3 + 1]);
+        assert_eq!(1.0, input[24 + 2]);
+    }
+
+    #[test]
+    fn test_mlp_fits_target() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for optimizer in [Optimizer::Sgd, Optimizer::adam()] {
+            let mut net = Mlp::new(&[2, 8, 1], &mut rng).unwrap();
+            net.optimizer = optimizer;
+            net.learning_rate = 0.05;
+            let input = [1.0, -1.0];
+            for _ in 0..500 {
+                let out = net.forward(&input)[0];
+                net.backward(&input, &[out - 0.5]);
+            }
+            assert!((net.forward(&input)[0] - 0.5).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let net = Mlp::new(&[3, 4, 2], &mut rng).unwrap();
+        let path = std::env::temp_dir().join("qtictactoe_test_mlp_weights.txt");
+        net.save(&path).unwrap();
+        let loaded = Mlp::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let input = [0.3, -0.2, 1.0];
+        assert_eq!(net.forward(&input), loaded.forward(&input));
+    }
+
+    #[test]
+    fn test_rejects_empty_layers() {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!(Mlp::new(&[3, 0, 2], &mut rng).is_err());
+        assert!(Mlp::new(&[3], &mut rng).is_err());
+        assert!(MlpQ::<3>::new(0, &mut rng).is_err());
+
+        let path = std::env::temp_dir().join("qtictactoe_test_mlp_empty.txt");
+        std::fs::write(&path, "0 2\n\n0 0\n").unwrap();
+        assert!(Mlp::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_from_network_checks_sizes() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let net = Mlp::new(&[27, 8, 9], &mut rng).unwrap();
+        assert!(MlpQ::<4>::from_network(net.clone()).is_err());
+        assert!(MlpQ::<3>::from_network(net).is_ok());
+        let net = Mlp::new(&[27, 8, 3], &mut rng).unwrap();
+        assert!(MlpQ::<3>::from_network(net).is_err());
+    }
+
+    #[test]
+    fn test_update_moves_towards_target() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut q = MlpQ::<3>::new(16, &mut rng).unwrap();
+        let b = Board::<3>::new();
+        let mut next = b;
+        next.board[0][0] = Piece::X;
+        next.board[1][1] = Piece::O;
+        let before = q.get(b, (0, 0));
+        for _ in 0..50 {
+            q.update(b, (0, 0), next, 100.0);
+        }
+        assert!(q.get(b, (0, 0)) > before);
+    }
+}
@@ -0,0 +1,153 @@
+//! A compact text notation for positions, in the spirit of chess's FEN. Rows are
+//! written top to bottom, separated by `/`, with `X`, `O` and `.` for the cells
+//! (a digit stands for that many empty cells). Then comes the side to move, `x`
+//! or `o`, and, if it is not the board size, how many in a row win as `k3`:
+//!
+//! ```
+//! use qtictactoe::{Board, Piece, Player};
+//!
+//! let board: Board<3> = "X.O/.X./..O x".parse().unwrap();
+//! assert_eq!(Piece::O, board.board[0][2]);
+//! assert_eq!(Player::X, board.player_to_move());
+//! assert_eq!("X.O/.X./..O x", board.notation());
+//!
+//! let big: Board<4> = "4/1X2/4/3O x k3".parse().unwrap();
+//! assert_eq!("..../.X../..../...O x k3", big.notation());
+//! ```
+
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::tic_tac_toe::{Board, Piece, Player};
+
+impl<const N: usize> Board<N> {
+    /// The position in the notation `from_str` reads
+    pub fn notation(&self) -> String {
+        let rows: Vec<String> = self
+            .board
+            .iter()
+            .map(|row| row.iter().map(|p| p.to_string()).collect())
+            .collect();
+        let side = match self.player_to_move() {
+            Player::X => 'x',
+            Player::O => 'o',
+        };
+        let mut out = format!("{} {side}", rows.join("/"));
+        if self.n as usize != N {
+            out.push_str(&format!(" k{}", self.n));
+        }
+        out
+    }
+}
+
+/// Reads a position in the notation `notation` writes. Letters can be either case.
+/// The position must pass `Board::validate`, and the side to move must be the one
+/// whose turn it is.
+impl<const N: usize> FromStr for Board<N> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = |msg: String| Error::Notation(format!("{msg} in {s:?}"));
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let (rows, side, rules) = match fields[..] {
+            [rows, side] => (rows, side, None),
+            [rows, side, rules] => (rows, side, Some(rules)),
+            _ => return Err(bad("Expected rows, side to move and maybe kK".to_string())),
+        };
+
+        let mut board = match rules {
+            None => Board::new(),
+            Some(rules) => {
+                let k = rules
+                    .strip_prefix(['k', 'K'])
+                    .and_then(|k| k.parse().ok())
+                    .filter(|k| (2..=N).contains(k))
+                    .ok_or_else(|| bad(format!("Expected k2 to k{N}, got {rules:?}")))?;
+                Board::with_win_length(k)
+            }
+        };
+
+        let rows: Vec<&str> = rows.split('/').collect();
+        if rows.len() != N {
+            return Err(bad(format!("Expected {N} rows, got {}", rows.len())));
+        }
+        for (row_num, row) in rows.into_iter().enumerate() {
+            let mut cells = Vec::with_capacity(N);
+            for c in row.chars() {
+                match c {
+                    'X' | 'x' => cells.push(Piece::X),
+                    'O' | 'o' => cells.push(Piece::O),
+                    '.' => cells.push(Piece::Empty),
+                    '1'..='9' => {
+                        let empties = c.to_digit(10).expect("A digit") as usize;
+                        cells.extend(std::iter::repeat_n(Piece::Empty, empties));
+                    }
+                    _ => return Err(bad(format!("Unexpected {c:?} in row {row_num}"))),
+                }
+            }
+            if cells.len() != N {
+                return Err(bad(format!(
+                    "Row {row_num} has {} cells instead of {N}",
+                    cells.len()
+                )));
+            }
+            board.board[row_num].copy_from_slice(&cells);
+        }
+
+        board.validate()?;
+        let side = match side {
+            "x" | "X" => Player::X,
+            "o" | "O" => Player::O,
+            _ => return Err(bad(format!("Expected x or o to move, got {side:?}"))),
+        };
+        if side != board.player_to_move() {
+            return Err(bad(format!("It is not {side:?}'s turn")));
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for s in [
+            ".../.../... x",
+            "X../.O./..X o",
+            "XOX/XOO/OXX o",
+            "XXX/OO./... o",
+        ] {
+            assert_eq!(s, s.parse::<Board<3>>().unwrap().notation());
+        }
+        let board: Board<3> = "x../.o./..x O".parse().unwrap();
+        assert_eq!(Piece::X, board.board[2][2]);
+        assert_eq!(Board::<3>::new(), "3/3/3 x".parse().unwrap());
+        assert_eq!(
+            Board::<5>::with_win_length(4),
+            "5/5/5/5/5 x k4".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rejects() {
+        for s in [
+            "",
+            "X../.../... o extra stuff",
+            "X../... o",
+            "X.../.../... o",
+            "X?./.../... o",
+            "XX./.../... o",
+            "XXX/OOO/... x",
+            "XXX/OO./O.. x",
+            "O../.../... x",
+            "X../.../... x",
+            "X../.../... y",
+            ".../.../... x k4",
+            ".../.../... x 3",
+        ] {
+            assert!(s.parse::<Board<3>>().is_err(), "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,716 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rustc_hash::FxHashMap;
+
+use crate::analysis;
+use crate::error::Error;
+use crate::q_matrix::{Q, QFunction};
+use crate::reward::RewardScheme;
+use crate::tic_tac_toe::{Board, GameResult, Piece, Player};
+
+/// Anything that can pick a move, e.g. the player an `Agent` trains against, or
+/// the computer player a human faces
+pub trait Opponent<const N: usize> {
+    /// The move `player` makes in `board`. There must be at least one empty spot.
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize);
+
+    /// A note on `player` moving at `action` in `board`, for game records, e.g. the
+    /// value this opponent puts on the move
+    fn annotate(
+        &self,
+        _board: &Board<N>,
+        _player: Player,
+        _action: (usize, usize),
+    ) -> Option<String> {
+        None
+    }
+
+    /// What this opponent thinks each move `player` can make in `board` is worth
+    /// to `player`, higher being better, e.g. for hints. None if it does not put
+    /// values on moves.
+    fn move_values(
+        &self,
+        _board: &Board<N>,
+        _player: Player,
+    ) -> Option<Vec<((usize, usize), f64)>> {
+        None
+    }
+}
+
+/// Closures can be used as opponents directly
+impl<const N: usize, F> Opponent<N> for F
+where
+    F: FnMut(&Board<N>, Player) -> (usize, usize),
+{
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        self(board, player)
+    }
+}
+
+/// Picks uniformly from the empty spots
+pub struct RandomOpponent {
+    pub rng: StdRng,
+}
+
+impl RandomOpponent {
+    /// A random opponent that always makes the same moves for the same seed
+    pub fn seeded(seed: u64) -> Self {
+        RandomOpponent {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// Seeded from the operating system, so every run is different
+impl Default for RandomOpponent {
+    fn default() -> Self {
+        RandomOpponent {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl<const N: usize> Opponent<N> for RandomOpponent {
+    fn choose_move(&mut self, board: &Board<N>, _player: Player) -> (usize, usize) {
+        *board
+            .get_empty_spots()
+            .choose(&mut self.rng)
+            .expect("Failed to notice that the game was over")
+    }
+}
+
+fn random_move<const N: usize>(board: &Board<N>, rng: &mut impl Rng) -> (usize, usize) {
+    *board
+        .get_empty_spots()
+        .choose(rng)
+        .expect("Failed to notice that the game was over")
+}
+
+/// Looks one move ahead: wins if it can, otherwise blocks the other player's
+/// winning move, otherwise plays randomly
+pub struct GreedyOpponent {
+    pub rng: StdRng,
+}
+
+impl GreedyOpponent {
+    pub fn seeded(seed: u64) -> Self {
+        GreedyOpponent {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for GreedyOpponent {
+    fn default() -> Self {
+        GreedyOpponent {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl<const N: usize> Opponent<N> for GreedyOpponent {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        board
+            .winning_move(player)
+            .or_else(|| board.winning_move(player.next_player()))
+            .unwrap_or_else(|| random_move(board, &mut self.rng))
+    }
+}
+
+/// The score for the player who just won `board`. Quicker wins leave more empty
+/// spots, so they score higher.
+fn win_score<const N: usize>(board: &Board<N>) -> i32 {
+    1 + board.count(Piece::Empty) as i32
+}
+
+/// The score of `action` for `player`, given a function scoring the board after it
+/// for the other player
+fn score_move<const N: usize>(
+    board: &Board<N>,
+    player: Player,
+    action: (usize, usize),
+    mut reply_score: impl FnMut(&Board<N>) -> i32,
+) -> i32 {
+    let mut next = *board;
+    match next.make_move(player, action.0, action.1) {
+        Some(GameResult::Tie) => 0,
+        // Only the player who just moved can have won
+        Some(_) => win_score(&next),
+        None => -reply_score(&next),
+    }
+}
+
+/// The moves in `scored` with the highest score
+fn best_of(scored: Vec<((usize, usize), i32)>) -> Vec<(usize, usize)> {
+    let best = scored.iter().map(|&(_, score)| score).max();
+    scored
+        .into_iter()
+        .filter(|&(_, score)| Some(score) == best)
+        .map(|(action, _)| action)
+        .collect()
+}
+
+/// Searches `depth` moves ahead with alpha-beta pruning. Positions not decided by
+/// then count as draws. Picks randomly between equally good moves.
+pub struct Minimax {
+    pub depth: usize,
+    pub rng: StdRng,
+}
+
+impl Minimax {
+    /// A depth of 0 still looks one move ahead
+    pub fn new(depth: usize, seed: u64) -> Self {
+        Minimax {
+            depth: depth.max(1),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// The score of `board` for `player`, who is to move, looking `depth` moves
+    /// ahead. Positive is a win, zero a draw and negative a loss.
+    fn negamax<const N: usize>(
+        board: &Board<N>,
+        player: Player,
+        depth: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        let mut best = i32::MIN;
+        for action in board.get_empty_spots() {
+            let score = score_move(board, player, action, |next| {
+                if depth <= 1 {
+                    0
+                } else {
+                    Self::negamax(next, player.next_player(), depth - 1, -beta, -alpha)
+                }
+            });
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+impl<const N: usize> Opponent<N> for Minimax {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        let depth = self.depth;
+        // Every move gets a full window, so that ties between moves are real
+        let scored = board
+            .get_empty_spots()
+            .into_iter()
+            .map(|action| {
+                let score = score_move(board, player, action, |next| {
+                    if depth <= 1 {
+                        0
+                    } else {
+                        Self::negamax(next, player.next_player(), depth - 1, -i32::MAX, i32::MAX)
+                    }
+                });
+                (action, score)
+            })
+            .collect();
+        *best_of(scored)
+            .choose(&mut self.rng)
+            .expect("Failed to notice that the game was over")
+    }
+}
+
+/// How a game ends with perfect play, for the player to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Outcome {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl Outcome {
+    /// The outcome of a `Solver` score
+    pub fn of(score: i32) -> Self {
+        match score.signum() {
+            1 => Outcome::Win,
+            0 => Outcome::Draw,
+            _ => Outcome::Loss,
+        }
+    }
+
+    /// The outcome for the other player
+    pub fn opposite(self) -> Self {
+        match self {
+            Outcome::Loss => Outcome::Win,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Win => Outcome::Loss,
+        }
+    }
+}
+
+/// How a position turns out with perfect play, for the player to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub outcome: Outcome,
+    /// How many moves are left in the game. The winner ends it as soon as it can,
+    /// and the loser holds out as long as it can.
+    pub distance: u8,
+}
+
+impl Entry {
+    /// Sorts entries from worst to best for the player to move
+    pub(crate) fn key(self) -> (Outcome, i16) {
+        match self.outcome {
+            Outcome::Loss => (Outcome::Loss, i16::from(self.distance)),
+            outcome => (outcome, -i16::from(self.distance)),
+        }
+    }
+
+    /// The entry for the player who makes the move leading to `self`
+    pub(crate) fn before(self) -> Entry {
+        Entry {
+            outcome: self.outcome.opposite(),
+            distance: self.distance + 1,
+        }
+    }
+}
+
+/// Written as e.g. `win in 3`
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {}", self.outcome, self.distance)
+    }
+}
+
+/// Anything that knows how every move turns out with perfect play
+pub trait PerfectPlay<const N: usize> {
+    /// How every move `player` can make in `board` turns out for `player`, or an
+    /// error if that is not known, e.g. a position missing from a tablebase
+    fn perfect_moves(
+        &mut self,
+        board: &Board<N>,
+        player: Player,
+    ) -> crate::error::Result<Vec<((usize, usize), Entry)>>;
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Outcome::Loss => "loss",
+            Outcome::Draw => "draw",
+            Outcome::Win => "win",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Plays perfectly, by solving the game from every position it sees. Solved
+/// positions are remembered, so this is fast on 3x3 boards, but bigger boards have
+/// far too many positions.
+pub struct Solver<const N: usize> {
+    values: FxHashMap<(Board<N>, Player), i32>,
+    pub rng: StdRng,
+}
+
+impl<const N: usize> Solver<N> {
+    pub fn new(seed: u64) -> Self {
+        Solver {
+            values: FxHashMap::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// The score of `board` for `player`, who is to move, with perfect play from
+    /// both sides. Positive is a win (higher for quicker wins), zero a draw and
+    /// negative a loss. The game must not be over.
+    pub fn value(&mut self, board: &Board<N>, player: Player) -> i32 {
+        if let Some(&value) = self.values.get(&(*board, player)) {
+            return value;
+        }
+        let value = self
+            .move_values(board, player)
+            .into_iter()
+            .map(|(_, score)| score)
+            .max()
+            .expect("Failed to notice that the game was over");
+        self.values.insert((*board, player), value);
+        value
+    }
+
+    /// The score of every move `player` can make in `board`
+    pub fn move_values(&mut self, board: &Board<N>, player: Player) -> Vec<((usize, usize), i32)> {
+        board
+            .get_empty_spots()
+            .into_iter()
+            .map(|action| {
+                let score = score_move(board, player, action, |next| {
+                    self.value(next, player.next_player())
+                });
+                (action, score)
+            })
+            .collect()
+    }
+
+    /// Every move that does as well as possible
+    pub fn best_moves(&mut self, board: &Board<N>, player: Player) -> Vec<(usize, usize)> {
+        best_of(self.move_values(board, player))
+    }
+}
+
+impl<const N: usize> PerfectPlay<N> for Solver<N> {
+    fn perfect_moves(
+        &mut self,
+        board: &Board<N>,
+        player: Player,
+    ) -> crate::error::Result<Vec<((usize, usize), Entry)>> {
+        let empty = board.count(Piece::Empty) as i32;
+        let entries = self
+            .move_values(board, player)
+            .into_iter()
+            .map(|(action, score)| {
+                let outcome = Outcome::of(score);
+                // A win or loss scores one more than the spots left empty at the end,
+                // and a draw fills the board
+                let distance = match outcome {
+                    Outcome::Draw => empty,
+                    _ => 1 + empty - score.abs(),
+                };
+                let distance = distance.try_into().expect("Fewer than 256 moves left");
+                (action, Entry { outcome, distance })
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+impl<const N: usize> Opponent<N> for Solver<N> {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        let best = self.best_moves(board, player);
+        *best
+            .choose(&mut self.rng)
+            .expect("Failed to notice that the game was over")
+    }
+}
+
+/// Plays the best moves of a Q table, or any other `QFunction`, that is no longer
+/// learning, e.g. a copy of an agent from earlier in training. Agents learn as X,
+/// so when playing O this looks one move ahead, as `QFunction::move_values` does.
+pub struct FrozenQ<const N: usize, L = Q<N>> {
+    pub q: L,
+    /// The rewards the table was learned with, which value moves that end the game
+    pub rewards: RewardScheme,
+    pub rng: StdRng,
+}
+
+impl<const N: usize, L> FrozenQ<N, L> {
+    /// A table learned with the classic rewards, the tabular learner's default
+    pub fn new(q: L, seed: u64) -> Self {
+        FrozenQ {
+            q,
+            rewards: RewardScheme::classic(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<const N: usize, L: QFunction<N>> Opponent<N> for FrozenQ<N, L> {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        if player == Player::O {
+            let values = self.q.move_values(*board, player, &self.rewards);
+            return *analysis::best_valued(&values)
+                .choose(&mut self.rng)
+                .expect("Failed to notice that the game was over");
+        }
+        match self.q.max_action_for_state(*board) {
+            (Some(action), _) => action,
+            (None, _) => random_move(board, &mut self.rng),
+        }
+    }
+
+    fn annotate(&self, board: &Board<N>, player: Player, action: (usize, usize)) -> Option<String> {
+        let (_, value) = self
+            .move_values(board, player)?
+            .into_iter()
+            .find(|&(a, _)| a == action)?;
+        Some(format!("q={value:.3}"))
+    }
+
+    fn move_values(&self, board: &Board<N>, player: Player) -> Option<Vec<((usize, usize), f64)>> {
+        Some(self.q.move_values(*board, player, &self.rewards))
+    }
+}
+
+/// Picks one of several opponents at random for every move, in proportion to
+/// their weights
+pub struct Mixture<const N: usize> {
+    pub opponents: Vec<(f64, Box<dyn Opponent<N> + Send>)>,
+    pub rng: StdRng,
+}
+
+impl<const N: usize> Mixture<N> {
+    pub fn new(opponents: Vec<(f64, Box<dyn Opponent<N> + Send>)>, seed: u64) -> Self {
+        assert!(
+            opponents.iter().any(|&(weight, _)| weight > 0.0),
+            "A mixture needs an opponent with a positive weight"
+        );
+        Mixture {
+            opponents,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<const N: usize> Opponent<N> for Mixture<N> {
+    fn choose_move(&mut self, board: &Board<N>, player: Player) -> (usize, usize) {
+        let dist = WeightedIndex::new(self.opponents.iter().map(|&(weight, _)| weight))
+            .expect("Weights are checked in new");
+        let idx = dist.sample(&mut self.rng);
+        self.opponents[idx].1.choose_move(board, player)
+    }
+}
+
+/// A description of an opponent, e.g. from the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpponentSpec {
+    Random,
+    Greedy,
+    /// Minimax searching this many moves ahead
+    Minimax(usize),
+    Solver,
+    /// Monte Carlo Tree Search, with this many iterations per move if given
+    Mcts(Option<usize>),
+    /// A frozen copy of a tabular agent trained for this many games against
+    /// random moves
+    Frozen(usize),
+    /// A frozen Q table saved with `Q::save`
+    Table(PathBuf),
+    /// Perfect play looked up in a table saved with `Tablebase::save`
+    Tablebase(PathBuf),
+    Mixture(Vec<(f64, OpponentSpec)>),
+}
+
+impl OpponentSpec {
+    fn parse_one(s: &str) -> Result<Self, Error> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let number = |arg: Option<&str>| {
+            arg.and_then(|a| a.trim().parse().ok())
+                .ok_or_else(|| Error::Config(format!("Expected {name}:NUMBER, got {s:?}")))
+        };
+        // A search with no depth or iterations has nothing to choose from
+        let positive = |arg: Option<&str>| match number(arg)? {
+            0 => Err(Error::Config(format!(
+                "{name} needs a number above 0, got {s:?}"
+            ))),
+            n => Ok(n),
+        };
+        match (name.trim(), arg) {
+            ("random", None) => Ok(OpponentSpec::Random),
+            ("greedy", None) => Ok(OpponentSpec::Greedy),
+            ("minimax", _) => Ok(OpponentSpec::Minimax(positive(arg)?)),
+            ("solver", None) => Ok(OpponentSpec::Solver),
+            ("mcts", None) => Ok(OpponentSpec::Mcts(None)),
+            ("mcts", _) => Ok(OpponentSpec::Mcts(Some(positive(arg)?))),
+            ("frozen", _) => Ok(OpponentSpec::Frozen(number(arg)?)),
+            ("table", Some(path)) if !path.is_empty() => Ok(OpponentSpec::Table(path.into())),
+            ("tablebase", Some(path)) if !path.is_empty() => {
+                Ok(OpponentSpec::Tablebase(path.into()))
+            }
+            _ => Err(Error::Config(format!(
+                "Expected random, greedy, minimax:DEPTH, solver, mcts, mcts:ITERS, \
+                 frozen:GAMES, table:FILE or tablebase:FILE, got {s:?}"
+            ))),
+        }
+    }
+}
+
+/// Writes the spec the way `from_str` reads it
+impl fmt::Display for OpponentSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpponentSpec::Random => write!(f, "random"),
+            OpponentSpec::Greedy => write!(f, "greedy"),
+            OpponentSpec::Minimax(depth) => write!(f, "minimax:{depth}"),
+            OpponentSpec::Solver => write!(f, "solver"),
+            OpponentSpec::Mcts(None) => write!(f, "mcts"),
+            OpponentSpec::Mcts(Some(iters)) => write!(f, "mcts:{iters}"),
+            OpponentSpec::Frozen(games) => write!(f, "frozen:{games}"),
+            OpponentSpec::Table(path) => write!(f, "table:{}", path.display()),
+            OpponentSpec::Tablebase(path) => write!(f, "tablebase:{}", path.display()),
+            OpponentSpec::Mixture(parts) => {
+                for (idx, (weight, spec)) in parts.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, "+")?;
+                    }
+                    write!(f, "{weight}*{spec}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses one opponent, e.g. `minimax:3`, or a weighted mixture of them, e.g.
+/// `0.8*random+0.2*solver`. Weights default to 1.
+impl FromStr for OpponentSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(['+', '*']) {
+            return Self::parse_one(s);
+        }
+        let parts = s
+            .split('+')
+            .map(|part| {
+                let (weight, spec) = match part.split_once('*') {
+                    Some((weight, spec)) => {
+                        let weight: f64 = weight.trim().parse().map_err(|_| {
+                            Error::Config(format!("Could not parse {weight:?} as a weight"))
+                        })?;
+                        (weight, spec)
+                    }
+                    None => (1.0, part),
+                };
+                if weight < 0.0 {
+                    return Err(Error::Config(format!(
+                        "Weights can not be negative, got {weight}"
+                    )));
+                }
+                Ok((weight, Self::parse_one(spec)?))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if parts.iter().all(|&(weight, _)| weight == 0.0) {
+            return Err(Error::Config(
+                "A mixture needs a positive weight".to_string(),
+            ));
+        }
+        Ok(OpponentSpec::Mixture(parts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// X has two in the top row, O has two in the middle row
+    fn both_threaten() -> Board<3> {
+        "XX./OO./... x".parse().unwrap()
+    }
+
+    /// O has two in the middle row and X must block
+    fn must_block() -> Board<3> {
+        "X../OO./.X. x".parse().unwrap()
+    }
+
+    #[test]
+    fn test_greedy() {
+        let mut greedy = GreedyOpponent::seeded(0);
+        assert_eq!((0, 2), greedy.choose_move(&both_threaten(), Player::X));
+        assert_eq!((1, 2), greedy.choose_move(&must_block(), Player::X));
+    }
+
+    #[test]
+    fn test_minimax() {
+        let mut minimax = Minimax::new(3, 0);
+        assert_eq!((0, 2), minimax.choose_move(&both_threaten(), Player::X));
+        assert_eq!((1, 2), minimax.choose_move(&must_block(), Player::X));
+    }
+
+    #[test]
+    fn test_solver() {
+        let mut solver = Solver::<3>::new(0);
+        // Tic-tac-toe is a draw with perfect play
+        assert_eq!(0, solver.value(&Board::new(), Player::X));
+        assert_eq!(vec![(0, 2)], solver.best_moves(&both_threaten(), Player::X));
+
+        // The solver never loses, whichever side it plays
+        let mut random = RandomOpponent::seeded(1);
+        for game in 0..20 {
+            let mut board = Board::<3>::new();
+            let mut player = Player::X;
+            let solver_player = if game % 2 == 0 { Player::X } else { Player::O };
+            let result = loop {
+                let action = if player == solver_player {
+                    solver.choose_move(&board, player)
+                } else {
+                    random.choose_move(&board, player)
+                };
+                if let Some(res) = board.make_move(player, action.0, action.1) {
+                    break res;
+                }
+                player = player.next_player();
+            };
+            let solver_lost = match solver_player {
+                Player::X => GameResult::OWon,
+                Player::O => GameResult::XWon,
+            };
+            assert_ne!(solver_lost, result);
+        }
+    }
+
+    #[test]
+    fn test_frozen_plays_o_by_looking_ahead() {
+        let mut agent = crate::Agent::<3>::builder().seed(3).build();
+        agent.learn(20_000);
+        let mut frozen = FrozenQ::new(agent.qlearner, 0);
+
+        // X threatens the top row, so O must block, and every position O can
+        // leave is one the table learned as X
+        let board: Board<3> = "XX./.O./... o".parse().unwrap();
+        for (row, col) in board.get_empty_spots() {
+            let mut next = board;
+            next.make_move(Player::O, row, col);
+            assert!(frozen.q.values.contains_key(&next), "{next}");
+        }
+        for _ in 0..10 {
+            assert_eq!((0, 2), frozen.choose_move(&board, Player::O));
+        }
+    }
+
+    #[test]
+    fn test_mixture_ignores_zero_weights() {
+        let mut mixture = Mixture::<3>::new(
+            vec![
+                (0.0, Box::new(RandomOpponent::seeded(0))),
+                (1.0, Box::new(GreedyOpponent::seeded(0))),
+            ],
+            0,
+        );
+        for _ in 0..20 {
+            assert_eq!((0, 2), mixture.choose_move(&both_threaten(), Player::X));
+        }
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(OpponentSpec::Random, "random".parse().unwrap());
+        assert_eq!(OpponentSpec::Minimax(3), "minimax:3".parse().unwrap());
+        assert_eq!(
+            OpponentSpec::Mixture(vec![
+                (0.8, OpponentSpec::Random),
+                (1.0, OpponentSpec::Frozen(500))
+            ]),
+            "0.8*random+frozen:500".parse().unwrap()
+        );
+        assert_eq!(
+            OpponentSpec::Table("runs/a.q".into()),
+            "table:runs/a.q".parse().unwrap()
+        );
+        for spec in ["mcts:500", "tablebase:t.bin", "0.5*greedy+0.5*minimax:2"] {
+            assert_eq!(spec, spec.parse::<OpponentSpec>().unwrap().to_string());
+        }
+        assert!("minimax".parse::<OpponentSpec>().is_err());
+        assert!("minimax:0".parse::<OpponentSpec>().is_err());
+        assert!("mcts:0".parse::<OpponentSpec>().is_err());
+        assert!("table:".parse::<OpponentSpec>().is_err());
+        assert!("perfect".parse::<OpponentSpec>().is_err());
+        assert!("-1*random".parse::<OpponentSpec>().is_err());
+        assert!("0*random+0*greedy".parse::<OpponentSpec>().is_err());
+    }
+}
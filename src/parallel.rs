@@ -0,0 +1,173 @@
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustc_hash::FxHashMap;
+
+use crate::agent::Agent;
+use crate::opponent::Opponent;
+use crate::q_matrix::Q;
+use crate::tic_tac_toe::Board;
+
+type Values<const N: usize> = FxHashMap<Board<N>, FxHashMap<(usize, usize), f64>>;
+type Visits<const N: usize> = FxHashMap<(Board<N>, (usize, usize)), u32>;
+
+/// Trains one tabular agent per thread, each against its own opponent. Every
+/// `sync_every` episodes the threads stop, and every agent's Q table is replaced
+/// with the average of all of them.
+///
+/// Each agent and opponent is seeded from `seed`, and tables are always averaged
+/// in the same order, so the result depends only on the seed and thread count.
+pub struct ParallelTrainer {
+    pub threads: usize,
+    /// How many episodes each thread plays between averages
+    pub sync_every: usize,
+    pub seed: u64,
+}
+
+impl ParallelTrainer {
+    pub fn new(threads: usize, seed: u64) -> Self {
+        ParallelTrainer {
+            threads: threads.max(1),
+            sync_every: 1_000,
+            seed,
+        }
+    }
+
+    /// Train for `n` episodes, split between the threads. `make_agent` is called
+    /// once per thread, and `make_opponent` once per thread with a seed for it.
+    /// Returns the first thread's agent, which holds the averaged table.
+    pub fn train<const N: usize>(
+        &self,
+        n: usize,
+        mut make_agent: impl FnMut() -> Agent<N>,
+        mut make_opponent: impl FnMut(u64) -> Box<dyn Opponent<N> + Send>,
+    ) -> Agent<N> {
+        let threads = self.threads.max(1);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let mut agent = make_agent();
+                agent.rng = StdRng::seed_from_u64(rng.r#gen());
+                (agent, make_opponent(rng.r#gen()))
+            })
+            .collect();
+        // The first threads play one more episode each if `n` does not divide evenly
+        let totals: Vec<usize> = (0..threads)
+            .map(|i| n / threads + usize::from(i < n % threads))
+            .collect();
+
+        let mut synced_visits = Visits::<N>::default();
+        let mut start = 0;
+        while start < totals[0] {
+            let end = start + self.sync_every.max(1);
+            thread::scope(|s| {
+                for ((agent, opponent), &total) in workers.iter_mut().zip(&totals) {
+                    s.spawn(move || {
+                        agent.learn_episodes(start..end.min(total), total, opponent.as_mut())
+                    });
+                }
+            });
+
+            let tables: Vec<_> = workers.iter().map(|(agent, _)| &agent.qlearner).collect();
+            let (values, visits) = average(&tables, &synced_visits);
+            for (agent, _) in &mut workers {
+                agent.qlearner.values = values.clone();
+                agent.qlearner.visits = visits.clone();
+            }
+            synced_visits = visits;
+            start = end;
+        }
+
+        workers.swap_remove(0).0
+    }
+}
+
+/// Combine tables that all started from the same values, with `synced_visits`.
+/// Each value is the average of the tables' values, weighted by how many times
+/// each table updated it since then. Visits are the total over every table.
+fn average<const N: usize>(tables: &[&Q<N>], synced_visits: &Visits<N>) -> (Values<N>, Visits<N>) {
+    // For every state and action: the weighted sum of values, the total weight, and
+    // the value to keep if no table updated it
+    let mut sums: FxHashMap<_, (f64, u32, f64)> = FxHashMap::default();
+    let mut visits = synced_visits.clone();
+    for table in tables {
+        for (&state, actions) in &table.values {
+            for (&action, &value) in actions {
+                let key = (state, action);
+                let before = synced_visits.get(&key).copied().unwrap_or(0);
+                let new_visits = table.visits.get(&key).copied().unwrap_or(0) - before;
+                let sum = sums.entry(key).or_insert((0.0, 0, value));
+                sum.0 += new_visits as f64 * value;
+                sum.1 += new_visits;
+                *visits.entry(key).or_insert(0) += new_visits;
+            }
+        }
+    }
+
+    let mut values = Values::<N>::default();
+    for ((state, action), (weighted, weight, unchanged)) in sums {
+        let value = if weight == 0 {
+            unchanged
+        } else {
+            weighted / weight as f64
+        };
+        values.entry(state).or_default().insert(action, value);
+    }
+    (values, visits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::RandomOpponent;
+    use crate::q_matrix::QFunction;
+    use crate::tic_tac_toe::Piece;
+
+    fn train(seed: u64) -> Agent<3> {
+        let mut trainer = ParallelTrainer::new(4, seed);
+        trainer.sync_every = 250;
+        trainer.train(4_000, Agent::new, |seed| {
+            Box::new(RandomOpponent::seeded(seed))
+        })
+    }
+
+    #[test]
+    fn test_average_weights_by_visits() {
+        let state = Board::<3>::new();
+        let mut next = state;
+        next.board[0][0] = Piece::X;
+        let mut a = Q::<3>::new();
+        let mut b = Q::<3>::new();
+        a.discount = 0.0;
+        a.alpha = 1.0;
+        b.discount = 0.0;
+        b.alpha = 1.0;
+        a.update(state, (0, 0), next, 3.0);
+        for _ in 0..3 {
+            b.update(state, (0, 0), next, 7.0);
+        }
+
+        let (values, visits) = average(&[&a, &b], &Visits::default());
+        assert_eq!(Some(&6.0), values[&state].get(&(0, 0)));
+        assert_eq!(Some(&4), visits.get(&(state, (0, 0))));
+    }
+
+    #[test]
+    fn test_same_seed_same_table() {
+        let a = train(3).qlearner;
+        let b = train(3).qlearner;
+        assert_eq!(a.visits, b.visits);
+        for (state, actions) in &a.values {
+            for (action, value) in actions {
+                assert_eq!(value.to_bits(), b.get(*state, *action).to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_agent_learns() {
+        let mut agent = train(0);
+        assert!(agent.win_rate(200, &mut RandomOpponent::seeded(1)) > 0.6);
+    }
+}
@@ -0,0 +1,221 @@
+use std::io::{BufRead, Write};
+
+use crate::analysis::{best_valued, value_map};
+use crate::error::Result;
+use crate::input::Input;
+use crate::opponent::Opponent;
+use crate::record::GameRecord;
+use crate::tic_tac_toe::{Board, GameResult, Player};
+
+/// How the computer's values for the human's moves are shown before each of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hints {
+    /// The values, as numbers on the board
+    Numbers,
+    /// The values, coloured from red for the worst to green for the best
+    Colour,
+}
+
+/// Whether to play another game after this one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    Again,
+    Quit,
+}
+
+/// The computer plays X and a human O, starting from whoever's turn it is in
+/// `start`, until the game ends or the human gives up or swaps sides. The human's
+/// moves and commands are read from `input`, and the board and messages written to
+/// `output`. With `hints`, the computer's value for each of the human's moves is
+/// shown before they move. Returns the record of the game, and whether the human
+/// wants to play on, which they do not once `input` runs out.
+///
+/// ```
+/// use qtictactoe::{Board, Next, RandomOpponent, play};
+///
+/// let mut computer = RandomOpponent::seeded(0);
+/// let mut output = Vec::new();
+/// let (record, next) = play::<3>(
+///     Board::new(),
+///     &mut computer,
+///     "random",
+///     None,
+///     &mut "resign\n".as_bytes(),
+///     &mut output,
+/// )
+/// .unwrap();
+/// assert_eq!(1, record.moves.len());
+/// assert_eq!(Next::Again, next);
+/// ```
+pub fn play<const N: usize>(
+    start: Board<N>,
+    computer: &mut dyn Opponent<N>,
+    name: &str,
+    hints: Option<Hints>,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<(GameRecord<N>, Next)> {
+    let mut record = GameRecord::new(name, "human", start);
+    let mut human = Player::O;
+    let mut game = start;
+    if game.player_to_move() == human {
+        writeln!(output, "{}", game)?;
+    }
+    'game: loop {
+        let player = game.player_to_move();
+        let (action, winner, annotation) = if player != human {
+            let action = computer.choose_move(&game, player);
+            let annotation = computer.annotate(&game, player, action);
+            (
+                action,
+                game.make_move(player, action.0, action.1),
+                annotation,
+            )
+        } else {
+            let values = computer.move_values(&game, player);
+            if let (Some(style), Some(values)) = (hints, &values) {
+                let colour = style == Hints::Colour;
+                write!(output, "{}", value_map(&game, values, colour))?;
+            }
+            writeln!(
+                output,
+                "Your move as {player:?}, e.g. 1,0, 1 0 or a2. Or help for commands"
+            )?;
+            loop {
+                let Some(command) = read_input::<N>(input, output)? else {
+                    return Ok((record, Next::Quit));
+                };
+                match command {
+                    Input::Move(row, col) => match game.try_move(player, row, col) {
+                        Ok(winner) => break ((row, col), winner, None),
+                        Err(err) => writeln!(output, "{err}")?,
+                    },
+                    Input::Hint => match &values {
+                        Some(values) => {
+                            let best: Vec<_> = best_valued(values)
+                                .iter()
+                                .map(|(row, col)| format!("{row},{col}"))
+                                .collect();
+                            writeln!(output, "Try {}", best.join(" or "))?;
+                        }
+                        None => writeln!(output, "No hints from {name}")?,
+                    },
+                    Input::Undo => {
+                        if !(0..record.moves.len()).any(|idx| record.mover(idx) == human) {
+                            writeln!(output, "You have no moves to take back")?;
+                            continue;
+                        }
+                        // Back to just before the human's last move
+                        while record.moves.pop().is_some() {
+                            if record.mover(record.moves.len()) == human {
+                                break;
+                            }
+                        }
+                        let positions = record.positions().expect("Every move was legal");
+                        game = *positions.last().expect("There is always the start");
+                        writeln!(output, "{}", game)?;
+                        continue 'game;
+                    }
+                    Input::Resign => {
+                        record.result = Some(match human {
+                            Player::X => GameResult::OWon,
+                            Player::O => GameResult::XWon,
+                        });
+                        writeln!(output, "=========== You Resigned ===========")?;
+                        return Ok((record, Next::Again));
+                    }
+                    Input::New => return Ok((record, Next::Again)),
+                    Input::Swap => {
+                        human = human.next_player();
+                        std::mem::swap(&mut record.x, &mut record.o);
+                        writeln!(output, "You are now {human:?}")?;
+                        continue 'game;
+                    }
+                    Input::Save(path) => match record.save(&path) {
+                        Ok(()) => writeln!(output, "Saved the game to {}", path.display())?,
+                        Err(err) => writeln!(output, "Could not save the game: {err}")?,
+                    },
+                    Input::Quit => return Ok((record, Next::Quit)),
+                    Input::Help => writeln!(output, "{}", Input::HELP)?,
+                }
+            }
+        };
+        record.push(action, annotation, winner);
+        writeln!(output, "{}", game)?;
+        if let Some(res) = winner {
+            let human_won = match res {
+                GameResult::XWon => Some(human == Player::X),
+                GameResult::OWon => Some(human == Player::O),
+                GameResult::Tie => None,
+            };
+            match human_won {
+                Some(false) => writeln!(output, "=========== You Lost ===========")?,
+                Some(true) => writeln!(output, "=========== You Won ===========")?,
+                None => writeln!(output, "=========== Tie ===========")?,
+            }
+            return Ok((record, Next::Again));
+        }
+    }
+}
+
+/// Read what the human types next, asking again until it is a move or command.
+/// None once there is no more input.
+fn read_input<const N: usize>(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<Input>> {
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        match Input::parse::<N>(&line) {
+            Ok(command) => return Ok(Some(command)),
+            Err(err) => writeln!(output, "{err}")?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Always plays the first empty spot
+    fn first_empty(board: &Board<3>, _player: Player) -> (usize, usize) {
+        board.get_empty_spots()[0]
+    }
+
+    fn run(typed: &str) -> (GameRecord<3>, Next, String) {
+        let mut output = Vec::new();
+        let (record, next) = play(
+            Board::new(),
+            &mut first_empty,
+            "first",
+            None,
+            &mut typed.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        (record, next, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_win() {
+        // X fills the top row from the left before O can finish a line
+        let (record, next, output) = run("1,1\n2,1\n");
+        assert_eq!(Some(GameResult::XWon), record.result);
+        assert_eq!(Next::Again, next);
+        assert!(output.contains("You Lost"));
+    }
+
+    #[test]
+    fn test_undo_and_bad_input() {
+        let (record, next, output) = run("nonsense\n0,0\n1,1\nundo\n");
+        assert!(output.contains("Did not understand"));
+        // X took the corner first
+        assert!(output.contains("Can not move at 0,0"));
+        // Undo takes back both 1,1 and X's reply
+        assert_eq!(1, record.moves.len());
+        assert_eq!(Next::Quit, next);
+    }
+}
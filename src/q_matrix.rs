@@ -1,10 +1,119 @@
+use std::fs;
+use std::path::Path;
+
 use rustc_hash::FxHashMap;
 
-use crate::tic_tac_toe::Board;
+use crate::error::{Error, Result};
+use crate::reward::RewardScheme;
+use crate::schedule::Schedule;
+use crate::tic_tac_toe::{Board, GameResult, Piece, Player};
+
+/// Something that can estimate the value of taking an action in a state, and
+/// learn from the rewards seen
+pub trait QFunction<const N: usize> {
+    /// The estimated value of taking `action` in `state`
+    fn get(&self, state: Board<N>, action: (usize, usize)) -> f64;
+
+    /// Get the action with highest reward, and the reward.
+    /// If there is no known action for `state`, then (None, 0.0).
+    fn max_action_for_state(&self, state: Board<N>) -> (Option<(usize, usize)>, f64);
+
+    /// Move the value of `action` in `state` towards
+    /// `reward + discount * max_action_for_state(next_state)`
+    fn update(
+        &mut self,
+        state: Board<N>,
+        action: (usize, usize),
+        next_state: Board<N>,
+        reward: f64,
+    );
+
+    /// Like `update`, but the move towards the target is scaled by `weight`, as
+    /// for importance sampling. By default this is done by shifting the reward,
+    /// so `update` sees a target `weight` times as far from the current value.
+    fn weighted_update(
+        &mut self,
+        state: Board<N>,
+        action: (usize, usize),
+        next_state: Board<N>,
+        reward: f64,
+        weight: f64,
+    ) {
+        let target = reward + self.discount() * self.max_action_for_state(next_state).1;
+        let error = target - self.get(state, action);
+        self.update(state, action, next_state, reward + (weight - 1.0) * error);
+    }
+
+    /// How much future rewards are discounted by in `update`
+    fn discount(&self) -> f64;
+
+    /// Called before every training episode, counting from zero, of `total`
+    fn start_episode(&mut self, _episode: usize, _total: usize) {}
 
+    /// How many states have a value stored. Zero for approximators that do not
+    /// store states.
+    fn n_states(&self) -> usize {
+        0
+    }
+
+    /// What each move `player` can make in `board` is worth to them. Learners only
+    /// see positions where X is to move, so as O each move is worth minus X's best
+    /// value in the position it leaves, or minus the reward X gets from `rewards`
+    /// if it ends the game.
+    fn move_values(
+        &self,
+        board: Board<N>,
+        player: Player,
+        rewards: &RewardScheme,
+    ) -> Vec<((usize, usize), f64)> {
+        board
+            .get_empty_spots()
+            .into_iter()
+            .map(|action| {
+                let value = match player {
+                    Player::X => self.get(board, action),
+                    Player::O => {
+                        let mut next = board;
+                        match next.make_move(player, action.0, action.1) {
+                            Some(GameResult::Tie) => -rewards.draw,
+                            Some(_) => -rewards.loss,
+                            None => -next
+                                .get_empty_spots()
+                                .into_iter()
+                                .map(|reply| self.get(next, reply))
+                                .fold(f64::NEG_INFINITY, f64::max),
+                        }
+                    }
+                };
+                (action, value)
+            })
+            .collect()
+    }
+}
+
+/// How far each update moves a value towards its target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepSize {
+    /// Always `alpha`
+    Constant,
+    /// `1 / visits(state, action) ^ omega`. For omega in (0.5, 1] this meets the
+    /// conditions for Q learning to converge.
+    Visits { omega: f64 },
+}
+
+#[derive(Clone)]
 pub struct Q<const N: usize> {
     pub alpha: f64,
     pub discount: f64,
+    /// If set, `alpha` and `discount` follow these over training
+    pub alpha_schedule: Option<Schedule>,
+    pub discount_schedule: Option<Schedule>,
+    pub step_size: StepSize,
+    /// How many times each state and action pair has been updated
+    pub visits: FxHashMap<(Board<N>, (usize, usize)), u32>,
+    /// The value of state and action pairs that have never been updated. Setting
+    /// this high makes untried moves look attractive.
+    pub initial_value: f64,
     pub values: FxHashMap<Board<N>, FxHashMap<(usize, usize), f64>>,
 }
 
@@ -13,18 +122,107 @@ impl<const N: usize> Q<N> {
         Q {
             alpha: 0.5,
             discount: 0.5,
+            alpha_schedule: None,
+            discount_schedule: None,
+            step_size: StepSize::Constant,
+            visits: FxHashMap::default(),
+            initial_value: 0.0,
             values: FxHashMap::default(),
         }
     }
+}
+
+impl<const N: usize> Q<N> {
+    /// Write every value as text: the board size, then a line per state and
+    /// action with the board row by row (`X`, `O` or `.`), the action, the value
+    /// and its visit count
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut out = format!("{N}\n");
+        for (state, actions) in &self.values {
+            let cells: String = state
+                .board
+                .iter()
+                .flatten()
+                .map(|p| p.to_string())
+                .collect();
+            for (&(row, col), value) in actions {
+                let visits = self.visits.get(&(*state, (row, col))).unwrap_or(&0);
+                out.push_str(&format!("{cells} {row} {col} {value} {visits}\n"));
+            }
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Read a table written by `save`. The learning settings are the defaults,
+    /// every state must be a position that can come up in a game, and every action
+    /// must be an empty cell in it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines().enumerate();
+        let size = lines.next().map(|(_, line)| line.trim());
+        if size != Some(N.to_string().as_str()) {
+            return Err(Error::Config(format!(
+                "Expected a table for a {N}x{N} board, got size {size:?}"
+            )));
+        }
 
+        let mut q = Q::new();
+        for (line_num, line) in lines {
+            let bad_line =
+                || Error::Config(format!("Line {}: could not parse {line:?}", line_num + 1));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [cells, row, col, value, visits] = fields[..] else {
+                return Err(bad_line());
+            };
+            let mut state = Board::<N>::new();
+            let mut pieces = cells.chars().map(|c| match c {
+                'X' => Some(Piece::X),
+                'O' => Some(Piece::O),
+                '.' => Some(Piece::Empty),
+                _ => None,
+            });
+            for spot in state.board.iter_mut().flatten() {
+                *spot = pieces.next().flatten().ok_or_else(bad_line)?;
+            }
+            if pieces.next().is_some() {
+                return Err(bad_line());
+            }
+            state.validate()?;
+            let action: (usize, usize) = (
+                row.parse().map_err(|_| bad_line())?,
+                col.parse().map_err(|_| bad_line())?,
+            );
+            if action.0 >= N || action.1 >= N || state.board[action.0][action.1] != Piece::Empty {
+                return Err(Error::Config(format!(
+                    "Line {}: {action:?} is not an empty cell",
+                    line_num + 1
+                )));
+            }
+            let value = value.parse().map_err(|_| bad_line())?;
+            q.values.entry(state).or_default().insert(action, value);
+            q.visits
+                .insert((state, action), visits.parse().map_err(|_| bad_line())?);
+        }
+        Ok(q)
+    }
+}
+
+impl<const N: usize> Default for Q<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> QFunction<N> for Q<N> {
     /// Even though the `.values` field is a double nested `HashMap`, this method
     /// makes it flat to the user.
-    /// It provides a default value of 0.0 if the entry does not exist
-    pub fn get(&self, state: Board<N>, action: (usize, usize)) -> f64 {
+    /// It provides a default value of `initial_value` if the entry does not exist
+    fn get(&self, state: Board<N>, action: (usize, usize)) -> f64 {
         match self.values.get(&state) {
-            None => 0.0,
+            None => self.initial_value,
             Some(action_map) => match action_map.get(&action) {
-                None => 0.0,
+                None => self.initial_value,
                 Some(val) => *val,
             },
         }
@@ -32,7 +230,7 @@ impl<const N: usize> Q<N> {
 
     /// Get the action with highest reward, and the reward.
     /// If state is not yet explored, then (None, 0.0).
-    pub fn max_action_for_state(&self, state: Board<N>) -> (Option<(usize, usize)>, f64) {
+    fn max_action_for_state(&self, state: Board<N>) -> (Option<(usize, usize)>, f64) {
         if let Some(action_map) = self.values.get(&state) {
             // There is at least one action entered for this state. Get the max value
             action_map.iter().fold((None, 0.0), |accum, item| {
@@ -47,7 +245,7 @@ impl<const N: usize> Q<N> {
         }
     }
 
-    pub fn update(
+    fn update(
         &mut self,
         state: Board<N>,
         action: (usize, usize),
@@ -60,24 +258,126 @@ impl<const N: usize> Q<N> {
         // Get the highest known value of the `next_state`
         let (_, next_q) = self.max_action_for_state(next_state);
 
-        let value = value + self.alpha * (reward + (self.discount * next_q) - value);
+        let visits = self.visits.entry((state, action)).or_insert(0);
+        *visits += 1;
+        let step = match self.step_size {
+            StepSize::Constant => self.alpha,
+            StepSize::Visits { omega } => 1.0 / (*visits as f64).powf(omega),
+        };
 
-        match self.values.get_mut(&state) {
-            // If None, then create an entry for this state and action with reward of 0
-            None => {
-                let mut new_action_map = FxHashMap::default();
-                new_action_map.insert(action, 0.0);
-                self.values.insert(state, new_action_map);
-            }
-            Some(action_map) => match action_map.get_mut(&action) {
-                // If None, then create an entry for this action with reward of 0
-                None => {
-                    action_map.insert(action, 0.0);
-                }
-                Some(val) => {
-                    *val = value;
-                }
-            },
+        let value = value + step * (reward + (self.discount * next_q) - value);
+
+        // Every update counts, including the first, so that visit based step sizes
+        // give an average of the targets seen
+        self.values.entry(state).or_default().insert(action, value);
+    }
+
+    fn discount(&self) -> f64 {
+        self.discount
+    }
+
+    fn start_episode(&mut self, episode: usize, total: usize) {
+        if let Some(schedule) = self.alpha_schedule {
+            self.alpha = schedule.value(episode, total);
         }
+        if let Some(schedule) = self.discount_schedule {
+            self.discount = schedule.value(episode, total);
+        }
+    }
+
+    fn n_states(&self) -> usize {
+        self.values.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tic_tac_toe::Piece;
+
+    fn next_state() -> Board<3> {
+        let mut next = Board::<3>::new();
+        next.board[0][0] = Piece::X;
+        next
+    }
+
+    #[test]
+    fn test_visit_counts() {
+        let mut q = Q::<3>::new();
+        let state = Board::<3>::new();
+        for _ in 0..3 {
+            q.update(state, (0, 0), next_state(), 1.0);
+        }
+        assert_eq!(Some(&3), q.visits.get(&(state, (0, 0))));
+        assert_eq!(None, q.visits.get(&(state, (1, 1))));
+    }
+
+    #[test]
+    fn test_visit_step_size_averages() {
+        // With omega = 1 the value is the average of the rewards
+        let mut q = Q::<3>::new();
+        q.step_size = StepSize::Visits { omega: 1.0 };
+        q.discount = 0.0;
+        let state = Board::<3>::new();
+        for reward in [0.0, 1.0, 2.0, 3.0] {
+            q.update(state, (0, 0), next_state(), reward);
+        }
+        assert_eq!(1.5, q.get(state, (0, 0)));
+    }
+
+    #[test]
+    fn test_weighted_update() {
+        let mut q = Q::<3>::new();
+        q.alpha = 1.0;
+        q.discount = 0.0;
+        let state = Board::<3>::new();
+        q.weighted_update(state, (0, 0), next_state(), 8.0, 0.25);
+        assert_eq!(2.0, q.get(state, (0, 0)));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut q = Q::<3>::new();
+        let state = Board::<3>::new();
+        q.update(state, (0, 0), next_state(), 1.0);
+        q.update(next_state(), (2, 1), state, -0.25);
+
+        let path = std::env::temp_dir().join("qtictactoe_test_table.txt");
+        q.save(&path).unwrap();
+        let loaded = Q::<3>::load(&path).unwrap();
+        assert!(Q::<4>::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(q.values, loaded.values);
+        assert_eq!(q.visits, loaded.visits);
+    }
+
+    #[test]
+    fn test_load_rejects_bad_actions() {
+        let path = std::env::temp_dir().join("qtictactoe_test_bad_table.txt");
+        for (line, ok) in [
+            ("......... 0 0 5.0 1", true),
+            // Off the board
+            ("......... 7 7 5.0 1", false),
+            // Already taken
+            ("X........ 0 0 5.0 1", false),
+        ] {
+            std::fs::write(&path, format!("3\n{line}\n")).unwrap();
+            assert_eq!(ok, Q::<3>::load(&path).is_ok(), "{line}");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_schedules() {
+        let mut q = Q::<3>::new();
+        q.alpha_schedule = Some(Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+        });
+        q.discount_schedule = Some(Schedule::Constant(0.9));
+        q.start_episode(25, 100);
+        assert_eq!(0.75, q.alpha);
+        assert_eq!(0.9, q.discount);
     }
 }
@@ -0,0 +1,352 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::tic_tac_toe::{Board, GameResult, Player};
+
+/// One move in a game record
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMove {
+    pub action: (usize, usize),
+    /// Anything worth noting about the move, e.g. the value the player put on it
+    pub annotation: Option<String>,
+}
+
+/// A game, written as text in the spirit of chess's PGN: a header line for each
+/// piece of metadata, then one line per move, with any annotation in braces.
+///
+/// ```text
+/// [X "agent"]
+/// [O "human"]
+/// [Size "3"]
+/// [WinLength "3"]
+/// [Start ".../.../... x"]
+/// [Date "2024-05-01"]
+/// [Result "X won"]
+///
+/// 1. X 1,1 {q=0.812}
+/// 2. O 0,0
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord<const N: usize> {
+    /// Who played X and who played O
+    pub x: String,
+    pub o: String,
+    /// When the game was played, as YYYY-MM-DD
+    pub date: String,
+    /// The position the game started from, which also sets the win length
+    pub start: Board<N>,
+    pub moves: Vec<RecordedMove>,
+    /// How the game ended, or None if it did not finish
+    pub result: Option<GameResult>,
+}
+
+impl<const N: usize> GameRecord<N> {
+    /// A record of a game between `x` and `o` from `start`, played today, with no
+    /// moves yet
+    pub fn new(x: impl Into<String>, o: impl Into<String>, start: Board<N>) -> Self {
+        GameRecord {
+            x: x.into(),
+            o: o.into(),
+            date: today(),
+            start,
+            moves: Vec::new(),
+            result: None,
+        }
+    }
+
+    /// Add a move, and the result if it ended the game
+    pub fn push(
+        &mut self,
+        action: (usize, usize),
+        annotation: Option<String>,
+        result: Option<GameResult>,
+    ) {
+        self.moves.push(RecordedMove { action, annotation });
+        self.result = result;
+    }
+
+    /// The board before the first move and after every move, checking that every
+    /// move is legal
+    pub fn positions(&self) -> Result<Vec<Board<N>>> {
+        let mut board = self.start;
+        let mut player = board.player_to_move();
+        let mut positions = vec![board];
+        for recorded in &self.moves {
+            board.try_move(player, recorded.action.0, recorded.action.1)?;
+            positions.push(board);
+            player = player.next_player();
+        }
+        Ok(positions)
+    }
+
+    /// Who made move `idx`, counting from zero
+    pub fn mover(&self, idx: usize) -> Player {
+        match (self.start.player_to_move(), idx % 2) {
+            (player, 0) => player,
+            (player, _) => player.next_player(),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+/// The board size in the `Size` header of a record, so it can be read as a
+/// `GameRecord` of that size
+pub fn record_size(text: &str) -> Result<usize> {
+    text.lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix("[Size \"")?.strip_suffix("\"]"))
+        .ok_or_else(|| Error::Config("The record has no Size header".to_string()))?
+        .parse()
+        .map_err(|_| Error::Config("The record's Size header is not a number".to_string()))
+}
+
+/// Today's date in UTC, as YYYY-MM-DD
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    // Days since 1970-01-01 to a calendar date, from Howard Hinnant's
+    // `civil_from_days`
+    let z = (seconds / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn result_name(result: Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::XWon) => "X won",
+        Some(GameResult::OWon) => "O won",
+        Some(GameResult::Tie) => "Tie",
+        None => "*",
+    }
+}
+
+impl<const N: usize> fmt::Display for GameRecord<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "[X \"{}\"]", self.x)?;
+        writeln!(f, "[O \"{}\"]", self.o)?;
+        writeln!(f, "[Size \"{N}\"]")?;
+        writeln!(f, "[WinLength \"{}\"]", self.start.n)?;
+        writeln!(f, "[Start \"{}\"]", self.start.notation())?;
+        writeln!(f, "[Date \"{}\"]", self.date)?;
+        writeln!(f, "[Result \"{}\"]", result_name(self.result))?;
+        writeln!(f)?;
+        for (idx, recorded) in self.moves.iter().enumerate() {
+            let (row, col) = recorded.action;
+            write!(f, "{}. {:?} {row},{col}", idx + 1, self.mover(idx))?;
+            if let Some(annotation) = &recorded.annotation {
+                write!(f, " {{{annotation}}}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a record written by `Display`. The moves must be legal.
+impl<const N: usize> FromStr for GameRecord<N> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut record = GameRecord::new("", "", Board::new());
+        record.date.clear();
+        let mut win_length = None;
+        for (line_num, line) in s.lines().enumerate() {
+            let bad = |msg: &str| Error::Config(format!("Line {}: {msg}", line_num + 1));
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let (key, value) = header
+                    .strip_suffix(']')
+                    .and_then(|h| h.split_once(' '))
+                    .and_then(|(key, value)| {
+                        Some((key, value.trim().strip_prefix('"')?.strip_suffix('"')?))
+                    })
+                    .ok_or_else(|| bad("expected [Key \"value\"]"))?;
+                match key {
+                    "X" => record.x = value.to_string(),
+                    "O" => record.o = value.to_string(),
+                    "Size" if value != N.to_string() => {
+                        return Err(bad(&format!("expected a {N}x{N} game, got size {value}")));
+                    }
+                    "WinLength" => win_length = Some(value.to_string()),
+                    "Start" => record.start = value.parse()?,
+                    "Date" => record.date = value.to_string(),
+                    "Result" => {
+                        record.result = match value {
+                            "X won" => Some(GameResult::XWon),
+                            "O won" => Some(GameResult::OWon),
+                            "Tie" => Some(GameResult::Tie),
+                            "*" => None,
+                            _ => return Err(bad("unknown result")),
+                        }
+                    }
+                    // Unknown headers, and a Size that matches, are ignored
+                    _ => {}
+                }
+                continue;
+            }
+
+            // e.g. `3. X 1,2 {q=0.5}`
+            let (text, annotation) = match line.split_once('{') {
+                Some((text, note)) => {
+                    let note = note.strip_suffix('}').ok_or_else(|| bad("unclosed {"))?;
+                    (text, Some(note.trim().to_string()))
+                }
+                None => (line, None),
+            };
+            let action = text
+                .split_whitespace()
+                .last()
+                .and_then(|cell| cell.split_once(','))
+                .and_then(|(row, col)| Some((row.parse().ok()?, col.parse().ok()?)))
+                .ok_or_else(|| bad("expected a move like `1. X 1,2`"))?;
+            record.moves.push(RecordedMove { action, annotation });
+        }
+
+        if win_length.is_some_and(|k| k != record.start.n.to_string()) {
+            return Err(Error::Config(
+                "WinLength does not match the Start position".to_string(),
+            ));
+        }
+        let last = *record.positions()?.last().expect("There is always a start");
+        let finished = match last.get_winner() {
+            Some(result) => Some(result),
+            None if last.is_ended() => Some(GameResult::Tie),
+            None => None,
+        };
+        // An unfinished game can still have been won by resignation
+        let resigned = finished.is_none() && record.result != Some(GameResult::Tie);
+        if record.result != finished && !resigned {
+            return Err(Error::Config(format!(
+                "The Result header is {:?}, but the moves {}",
+                result_name(record.result),
+                match finished {
+                    Some(result) => format!("end with {:?}", result_name(Some(result))),
+                    None => "do not finish the game".to_string(),
+                }
+            )));
+        }
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> GameRecord<3> {
+        let mut record = GameRecord::new("agent", "human", Board::new());
+        record.push((1, 1), Some("q=0.812".to_string()), None);
+        record.push((0, 0), None, None);
+        record.push((0, 2), None, None);
+        record.push((2, 0), None, None);
+        record.push((1, 2), None, None);
+        record.push((1, 0), None, Some(GameResult::OWon));
+        record
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let record = sample();
+        let text = record.to_string();
+        assert!(text.contains("1. X 1,1 {q=0.812}\n2. O 0,0\n"));
+        assert!(text.contains("[Result \"O won\"]"));
+        assert_eq!(record, text.parse().unwrap());
+
+        let path = std::env::temp_dir().join("qtictactoe_test_record.txt");
+        record.save(&path).unwrap();
+        assert_eq!(record, GameRecord::load(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_positions() {
+        let positions = sample().positions().unwrap();
+        assert_eq!(7, positions.len());
+        assert_eq!(Some(GameResult::OWon), positions[6].get_winner());
+
+        let mut record = GameRecord::<3>::new("a", "b", "X../.../... o".parse().unwrap());
+        record.push((0, 0), None, None);
+        assert!(record.positions().is_err());
+        assert_eq!(Player::O, record.mover(0));
+    }
+
+    #[test]
+    fn test_rejects() {
+        let text = sample().to_string();
+        assert!(
+            text.replace("2. O 0,0", "2. O 1,1")
+                .parse::<GameRecord<3>>()
+                .is_err()
+        );
+        assert!(text.parse::<GameRecord<4>>().is_err());
+        assert!(
+            text.replace("O won", "who knows")
+                .parse::<GameRecord<3>>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_result_must_match() {
+        let text = sample().to_string();
+        for (result, ok) in [
+            ("O won", true),
+            ("X won", false),
+            ("Tie", false),
+            ("*", false),
+        ] {
+            let text = text.replace("O won", result);
+            assert_eq!(ok, text.parse::<GameRecord<3>>().is_ok(), "{result}");
+        }
+        // Unfinished games are either still going or resigned
+        let text = text.replace("6. O 1,0\n", "");
+        for (result, ok) in [
+            ("O won", true),
+            ("X won", true),
+            ("Tie", false),
+            ("*", true),
+        ] {
+            let text = text.replace("O won", result);
+            assert_eq!(ok, text.parse::<GameRecord<3>>().is_ok(), "{result}");
+        }
+    }
+
+    #[test]
+    fn test_record_size() {
+        assert_eq!(3, record_size(&sample().to_string()).unwrap());
+        let text = GameRecord::<5>::new("a", "b", Board::new()).to_string();
+        assert_eq!(5, record_size(&text).unwrap());
+        assert!(record_size("[X \"a\"]").is_err());
+    }
+
+    #[test]
+    fn test_today() {
+        let date = today();
+        assert_eq!(10, date.len());
+        assert!(date.as_str() > "2024-01-01");
+    }
+}
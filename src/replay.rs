@@ -0,0 +1,284 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::dyna::Transition;
+use crate::q_matrix::QFunction;
+
+/// How transitions are picked from a `ReplayBuffer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Every stored transition is equally likely
+    Uniform,
+    /// Transitions are picked with probability proportional to
+    /// `(|TD error| + eps) ^ alpha`, so surprising ones are replayed more often.
+    /// Each update is weighted by `(len * probability) ^ -beta`, scaled so the
+    /// largest in the batch is 1, to make up for the bias this brings.
+    Prioritised { alpha: f64, eps: f64, beta: f64 },
+}
+
+impl Sampling {
+    /// Prioritised sampling with the usual defaults
+    pub fn prioritised() -> Self {
+        Sampling::Prioritised {
+            alpha: 0.6,
+            eps: 0.01,
+            beta: 0.4,
+        }
+    }
+}
+
+/// A binary tree where every node holds the sum of its children, so leaves can be
+/// changed and picked in proportion to their values in `O(log capacity)`
+struct SumTree {
+    // The root is at 1, the children of `i` at `2i` and `2i + 1`, and the leaves
+    // from `leaves` on
+    nodes: Vec<f64>,
+    leaves: usize,
+}
+
+impl SumTree {
+    fn new(capacity: usize) -> Self {
+        let leaves = capacity.next_power_of_two();
+        SumTree {
+            nodes: vec![0.0; 2 * leaves],
+            leaves,
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[1]
+    }
+
+    fn get(&self, idx: usize) -> f64 {
+        self.nodes[self.leaves + idx]
+    }
+
+    fn set(&mut self, idx: usize, value: f64) {
+        let mut node = self.leaves + idx;
+        self.nodes[node] = value;
+        while node > 1 {
+            node /= 2;
+            self.nodes[node] = self.nodes[2 * node] + self.nodes[2 * node + 1];
+        }
+    }
+
+    /// The leaf where the running total of values passes `mass`, which should be
+    /// below `total()`
+    fn find(&self, mut mass: f64) -> usize {
+        let mut node = 1;
+        while node < self.leaves {
+            let left = 2 * node;
+            if mass < self.nodes[left] || self.nodes[left + 1] == 0.0 {
+                node = left;
+            } else {
+                mass -= self.nodes[left];
+                node = left + 1;
+            }
+        }
+        node - self.leaves
+    }
+}
+
+/// Stores the most recent transitions, up to `capacity` of them, and replays
+/// mini-batches of them through a `QFunction`. Sampling uses its own seeded
+/// random number generator, so the same seed always picks the same transitions.
+pub struct ReplayBuffer<const N: usize> {
+    pub capacity: usize,
+    pub sampling: Sampling,
+    transitions: Vec<Transition<N>>,
+    // Every transition's priority to the power of alpha
+    priorities: SumTree,
+    // The highest priority given so far, which new transitions start with
+    max_priority: f64,
+    // Where the next transition goes once the buffer is full
+    next: usize,
+    rng: StdRng,
+}
+
+impl<const N: usize> ReplayBuffer<N> {
+    /// An empty buffer. Panics if `capacity` is zero.
+    pub fn new(capacity: usize, sampling: Sampling, seed: u64) -> Self {
+        assert!(capacity > 0, "A replay buffer needs room for a transition");
+        ReplayBuffer {
+            capacity,
+            sampling,
+            transitions: Vec::with_capacity(capacity),
+            priorities: SumTree::new(capacity),
+            max_priority: 1.0,
+            next: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Store `transition`, replacing the oldest one if the buffer is full. New
+    /// transitions get the highest priority seen, so they are replayed at least once.
+    pub fn push(&mut self, transition: Transition<N>) {
+        let idx = if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+            self.transitions.len() - 1
+        } else {
+            let idx = self.next;
+            self.transitions[idx] = transition;
+            self.next = (self.next + 1) % self.capacity;
+            idx
+        };
+        self.set_priority(idx, self.max_priority);
+    }
+
+    fn set_priority(&mut self, idx: usize, priority: f64) {
+        self.max_priority = self.max_priority.max(priority);
+        if let Sampling::Prioritised { alpha, .. } = self.sampling {
+            self.priorities.set(idx, priority.powf(alpha));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    /// The indices of `batch_size` transitions, picked with replacement, each with
+    /// the weight its update should get. Weights are all 1 for uniform sampling.
+    pub fn sample(&mut self, batch_size: usize) -> Vec<(usize, f64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        match self.sampling {
+            Sampling::Uniform => (0..batch_size)
+                .map(|_| (self.rng.gen_range(0..self.transitions.len()), 1.0))
+                .collect(),
+            Sampling::Prioritised { beta, .. } => {
+                let total = self.priorities.total();
+                let picks: Vec<(usize, f64)> = (0..batch_size)
+                    .map(|_| {
+                        let idx = self.priorities.find(self.rng.gen_range(0.0..total));
+                        let probability = self.priorities.get(idx) / total;
+                        (idx, (self.len() as f64 * probability).powf(-beta))
+                    })
+                    .collect();
+                let max_weight = picks.iter().map(|&(_, w)| w).fold(0.0, f64::max);
+                picks
+                    .into_iter()
+                    .map(|(idx, w)| (idx, w / max_weight))
+                    .collect()
+            }
+        }
+    }
+
+    /// Sample a mini-batch and update `qlearner` with every transition in it
+    pub fn replay(&mut self, qlearner: &mut impl QFunction<N>, batch_size: usize) {
+        for (idx, weight) in self.sample(batch_size) {
+            let t = self.transitions[idx];
+            if let Sampling::Prioritised { eps, .. } = self.sampling {
+                self.set_priority(idx, td_error(qlearner, &t).abs() + eps);
+            }
+            qlearner.weighted_update(t.state, t.action, t.next_state, t.reward, weight);
+        }
+    }
+}
+
+/// How far the current estimate for `t` is from its one step target
+pub fn td_error<const N: usize>(qlearner: &impl QFunction<N>, t: &Transition<N>) -> f64 {
+    let next_q = if t.done {
+        0.0
+    } else {
+        qlearner.max_action_for_state(t.next_state).1
+    };
+    t.reward + qlearner.discount() * next_q - qlearner.get(t.state, t.action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::q_matrix::Q;
+    use crate::tic_tac_toe::{Board, Piece};
+
+    fn transition(col: usize, reward: f64) -> Transition<3> {
+        let state = Board::<3>::new();
+        let mut next_state = state;
+        next_state.board[0][col] = Piece::X;
+        Transition {
+            state,
+            action: (0, col),
+            next_state,
+            reward,
+            done: false,
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut buffer = ReplayBuffer::new(2, Sampling::Uniform, 0);
+        for col in 0..3 {
+            buffer.push(transition(col, 0.0));
+        }
+        assert_eq!(2, buffer.len());
+        // The oldest transition was replaced
+        assert_eq!((0, 2), buffer.transitions[0].action);
+    }
+
+    #[test]
+    fn test_seeded_sampling() {
+        let mut a = ReplayBuffer::new(10, Sampling::Uniform, 42);
+        let mut b = ReplayBuffer::new(10, Sampling::Uniform, 42);
+        for col in 0..3 {
+            a.push(transition(col, 0.0));
+            b.push(transition(col, 0.0));
+        }
+        assert_eq!(a.sample(20), b.sample(20));
+    }
+
+    #[test]
+    fn test_prioritised_prefers_large_errors() {
+        let mut buffer = ReplayBuffer::new(10, Sampling::prioritised(), 1);
+        buffer.push(transition(0, 0.0));
+        buffer.push(transition(1, 100.0));
+        let mut q = Q::<3>::new();
+        // Learn the priorities, then check the surprising transition dominates
+        buffer.replay(&mut q, 10);
+        let picks = buffer.sample(1_000);
+        let n_surprising = picks.iter().filter(|&&(idx, _)| idx == 1).count();
+        assert!(n_surprising > 700);
+        // Rarely picked transitions get the larger weights
+        let weight = |i| picks.iter().find(|&&(idx, _)| idx == i).unwrap().1;
+        assert_eq!(1.0, weight(0));
+        assert!(weight(1) < 1.0);
+    }
+
+    #[test]
+    fn test_sum_tree() {
+        let mut tree = SumTree::new(3);
+        for (idx, value) in [1.0, 0.0, 3.0].into_iter().enumerate() {
+            tree.set(idx, value);
+        }
+        assert_eq!(4.0, tree.total());
+        assert_eq!(0, tree.find(0.5));
+        assert_eq!(2, tree.find(1.0));
+        assert_eq!(2, tree.find(3.9));
+        tree.set(2, 1.0);
+        assert_eq!(2.0, tree.total());
+    }
+
+    #[test]
+    fn test_max_priority_is_kept() {
+        let mut buffer = ReplayBuffer::new(1, Sampling::prioritised(), 0);
+        buffer.push(transition(0, 100.0));
+        buffer.replay(&mut Q::<3>::new(), 1);
+        // The big error outlives the transition that had it
+        buffer.push(transition(1, 0.0));
+        assert!(buffer.max_priority > 100.0);
+        assert_eq!(buffer.max_priority.powf(0.6), buffer.priorities.total());
+    }
+
+    #[test]
+    fn test_replay_updates_q() {
+        let mut buffer = ReplayBuffer::new(10, Sampling::Uniform, 0);
+        buffer.push(transition(0, 100.0));
+        let mut q = Q::<3>::new();
+        buffer.replay(&mut q, 5);
+        assert!(q.get(Board::new(), (0, 0)) > 0.0);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::tic_tac_toe::{GameResult, Player};
+
+/// The rewards a learner gets for how a game ends, and for each move it makes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RewardScheme {
+    pub win: f64,
+    pub loss: f64,
+    pub draw: f64,
+    /// Given for every move, on top of any reward for ending the game
+    pub per_move: f64,
+    /// Given for trying to play in a spot that is already taken
+    pub illegal_move: f64,
+}
+
+impl RewardScheme {
+    /// The scale the tabular learner has always used: +100 for a win and -100
+    /// for a loss
+    pub fn classic() -> Self {
+        RewardScheme {
+            win: 100.0,
+            loss: -100.0,
+            draw: 0.0,
+            per_move: 0.0,
+            illegal_move: -100.0,
+        }
+    }
+
+    /// Win 1, draw 0.5, loss 0, so values are the expected score of a game
+    pub fn unit() -> Self {
+        RewardScheme {
+            win: 1.0,
+            loss: 0.0,
+            draw: 0.5,
+            per_move: 0.0,
+            illegal_move: -1.0,
+        }
+    }
+
+    /// Win 1, draw 0, loss -1
+    pub fn symmetric() -> Self {
+        RewardScheme {
+            win: 1.0,
+            loss: -1.0,
+            draw: 0.0,
+            per_move: 0.0,
+            illegal_move: -1.0,
+        }
+    }
+
+    /// One of the presets by name: classic, unit or symmetric
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "unit" => Some(Self::unit()),
+            "symmetric" => Some(Self::symmetric()),
+            _ => None,
+        }
+    }
+
+    /// The reward `player` gets when the game ends in `result`
+    pub fn for_result(&self, result: GameResult, player: Player) -> f64 {
+        match (result, player) {
+            (GameResult::Tie, _) => self.draw,
+            (GameResult::XWon, Player::X) | (GameResult::OWon, Player::O) => self.win,
+            _ => self.loss,
+        }
+    }
+
+    /// The reward for a move, given how the game stood after it (and any reply)
+    pub fn for_move(&self, result: Option<GameResult>, player: Player) -> f64 {
+        self.per_move + result.map_or(0.0, |res| self.for_result(res, player))
+    }
+
+    /// Change this scheme with the settings in a config file. Each line is
+    /// `key = value`, where the keys are `preset` (which replaces every value) and
+    /// the names of the fields. Blank lines and lines starting with `#` are skipped.
+    pub fn apply_config(&mut self, text: &str) -> Result<()> {
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| config_error(line_num, "expected `key = value`".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "preset" {
+                *self = Self::preset(value)
+                    .ok_or_else(|| config_error(line_num, format!("unknown preset {value:?}")))?;
+                continue;
+            }
+            let value: f64 = value
+                .parse()
+                .map_err(|_| config_error(line_num, format!("could not parse {value:?}")))?;
+            match key {
+                "win" => self.win = value,
+                "loss" => self.loss = value,
+                "draw" => self.draw = value,
+                "per_move" => self.per_move = value,
+                "illegal_move" => self.illegal_move = value,
+                _ => return Err(config_error(line_num, format!("unknown key {key:?}"))),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An error on the line at index `line_num` of a config file
+fn config_error(line_num: usize, msg: String) -> Error {
+    Error::Config(format!("Line {}: {msg}", line_num + 1))
+}
+
+impl fmt::Display for RewardScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "win {}, loss {}, draw {}, per move {}, illegal move {}",
+            self.win, self.loss, self.draw, self.per_move, self.illegal_move
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_is_not_a_win() {
+        let rewards = RewardScheme::unit();
+        assert_eq!(0.5, rewards.for_result(GameResult::Tie, Player::X));
+        assert_eq!(0.5, rewards.for_result(GameResult::Tie, Player::O));
+        assert_eq!(1.0, rewards.for_result(GameResult::OWon, Player::O));
+        assert_eq!(0.0, rewards.for_result(GameResult::OWon, Player::X));
+    }
+
+    #[test]
+    fn test_for_move() {
+        let mut rewards = RewardScheme::symmetric();
+        rewards.per_move = -0.1;
+        assert_eq!(-0.1, rewards.for_move(None, Player::X));
+        assert_eq!(0.9, rewards.for_move(Some(GameResult::XWon), Player::X));
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut rewards = RewardScheme::classic();
+        let config = "# Rewards for the unit preset, but ties are bad\n\
+                      preset = unit\n\
+                      \n\
+                      draw = -0.25\n";
+        rewards.apply_config(config).unwrap();
+        assert_eq!(
+            RewardScheme {
+                draw: -0.25,
+                ..RewardScheme::unit()
+            },
+            rewards
+        );
+
+        assert!(rewards.apply_config("win: 1").is_err());
+        assert!(rewards.apply_config("tie = 1").is_err());
+        assert!(rewards.apply_config("preset = huge").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// How a hyperparameter, such as epsilon or a temperature, changes over training
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// The same value for every episode
+    Constant(f64),
+    /// Moves in a straight line from `start` to `end` over all the episodes
+    Linear { start: f64, end: f64 },
+    /// `start * decay ^ episode`
+    Exponential { start: f64, decay: f64 },
+    /// `start * factor ^ (episode / every)`, i.e. drops by `factor` every `every`
+    /// episodes
+    Step {
+        start: f64,
+        factor: f64,
+        every: usize,
+    },
+}
+
+impl Schedule {
+    /// The value for `episode` (counting from zero) of `total` episodes
+    pub fn value(&self, episode: usize, total: usize) -> f64 {
+        match *self {
+            Schedule::Constant(value) => value,
+            Schedule::Linear { start, end } => {
+                start + (end - start) * episode as f64 / total.max(1) as f64
+            }
+            Schedule::Exponential { start, decay } => start * decay.powf(episode as f64),
+            Schedule::Step {
+                start,
+                factor,
+                every,
+            } => start * factor.powf((episode / every.max(1)) as f64),
+        }
+    }
+}
+
+/// Parses `constant:V` (or just `V`), `linear:START,END`, `exponential:START,DECAY`
+/// and `step:START,FACTOR,EVERY`
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, params) = s.split_once(':').unwrap_or(("constant", s));
+        let params: Vec<f64> = params
+            .split(',')
+            .map(|p| {
+                p.trim()
+                    .parse()
+                    .map_err(|_| Error::Config(format!("Could not parse {p:?} as a number")))
+            })
+            .collect::<Result<_, _>>()?;
+
+        match (kind, params.as_slice()) {
+            ("constant", &[value]) => Ok(Schedule::Constant(value)),
+            ("linear", &[start, end]) => Ok(Schedule::Linear { start, end }),
+            ("exponential", &[start, decay]) => Ok(Schedule::Exponential { start, decay }),
+            ("step", &[start, factor, every]) if every >= 1.0 => Ok(Schedule::Step {
+                start,
+                factor,
+                every: every as usize,
+            }),
+            _ => Err(Error::Config(format!(
+                "Expected constant:V, linear:START,END, exponential:START,DECAY or \
+                 step:START,FACTOR,EVERY, got {s:?}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedules() {
+        assert_eq!(0.3, Schedule::Constant(0.3).value(50, 100));
+
+        let linear = Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+        };
+        assert_eq!(1.0, linear.value(0, 100));
+        assert_eq!(0.5, linear.value(50, 100));
+
+        let exponential = Schedule::Exponential {
+            start: 1.0,
+            decay: 0.5,
+        };
+        assert_eq!(0.25, exponential.value(2, 100));
+
+        let step = Schedule::Step {
+            start: 1.0,
+            factor: 0.5,
+            every: 10,
+        };
+        assert_eq!(1.0, step.value(9, 100));
+        assert_eq!(0.5, step.value(10, 100));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Schedule::Constant(0.1), "0.1".parse().unwrap());
+        assert_eq!(
+            Schedule::Linear {
+                start: 1.0,
+                end: 0.0
+            },
+            "linear:1,0".parse().unwrap()
+        );
+        assert_eq!(
+            Schedule::Step {
+                start: 1.0,
+                factor: 0.5,
+                every: 100
+            },
+            "step:1, 0.5, 100".parse().unwrap()
+        );
+        assert!("linear:1".parse::<Schedule>().is_err());
+        assert!("cubic:1,2".parse::<Schedule>().is_err());
+    }
+}
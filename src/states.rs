@@ -0,0 +1,234 @@
+use std::fmt;
+
+use rustc_hash::FxHashSet;
+
+use crate::tic_tac_toe::{Board, GameResult, Player};
+
+/// How many ways there are to turn or flip a square board onto itself
+pub const SYMMETRIES: usize = 8;
+
+/// Where `cell` ends up under symmetry `sym`, which counts from 0, the identity, to
+/// `SYMMETRIES - 1`. 1 to 3 turn the board clockwise a quarter turn at a time, and
+/// 4 to 7 do the same after flipping it left to right.
+pub fn map_cell<const N: usize>(sym: usize, (row, col): (usize, usize)) -> (usize, usize) {
+    let col = if sym >= 4 { N - 1 - col } else { col };
+    match sym % 4 {
+        0 => (row, col),
+        1 => (col, N - 1 - row),
+        2 => (N - 1 - row, N - 1 - col),
+        _ => (N - 1 - col, row),
+    }
+}
+
+/// Undoes `map_cell(sym, _)`. Flips are their own inverse, as are half turns.
+pub fn unmap_cell<const N: usize>(sym: usize, cell: (usize, usize)) -> (usize, usize) {
+    let inverse = match sym {
+        1 => 3,
+        3 => 1,
+        sym => sym,
+    };
+    map_cell::<N>(inverse, cell)
+}
+
+/// `board` turned or flipped by symmetry `sym`
+pub fn transform<const N: usize>(sym: usize, board: &Board<N>) -> Board<N> {
+    let mut out = *board;
+    for row in 0..N {
+        for col in 0..N {
+            let (r, c) = map_cell::<N>(sym, (row, col));
+            out.board[r][c] = board.board[row][col];
+        }
+    }
+    out
+}
+
+/// The same representative of every board that the symmetries turn into each
+/// other, and the symmetry that turns `board` into it
+pub fn canonical<const N: usize>(board: &Board<N>) -> (Board<N>, usize) {
+    (0..SYMMETRIES)
+        .map(|sym| (transform(sym, board), sym))
+        .min_by(|(a, _), (b, _)| a.board.cmp(&b.board))
+        .expect("There is always the identity")
+}
+
+/// Every position reachable from `start` by legal moves, including `start` and the
+/// finished games. If `modulo_symmetry`, only one of every set of positions the
+/// symmetries turn into each other is kept, as its `canonical` form.
+pub fn reachable<const N: usize>(start: Board<N>, modulo_symmetry: bool) -> Vec<Board<N>> {
+    let key = |board: Board<N>| {
+        if modulo_symmetry {
+            canonical(&board).0
+        } else {
+            board
+        }
+    };
+    let mut seen = FxHashSet::default();
+    let mut stack = vec![key(start)];
+    let mut positions = Vec::new();
+    while let Some(board) = stack.pop() {
+        if !seen.insert(board) {
+            continue;
+        }
+        positions.push(board);
+        if board.get_winner().is_some() || board.is_ended() {
+            continue;
+        }
+        let player = board.player_to_move();
+        for (row, col) in board.get_empty_spots() {
+            let mut next = board;
+            next.make_move(player, row, col);
+            stack.push(key(next));
+        }
+    }
+    positions
+}
+
+/// The positions reachable from `start` where the game is not over and X is to
+/// move. These are the states an agent, which always plays X, learns values for.
+pub fn decision_states<const N: usize>(start: Board<N>) -> Vec<Board<N>> {
+    reachable(start, false)
+        .into_iter()
+        .filter(|b| b.get_winner().is_none() && !b.is_ended())
+        .filter(|b| b.player_to_move() == Player::X)
+        .collect()
+}
+
+/// How many complete move sequences of `depth` moves there are from `board`,
+/// counting games that end sooner once. A test of the move generator in the style
+/// of chess engines' perft.
+pub fn perft<const N: usize>(board: &Board<N>, depth: usize) -> u64 {
+    if depth == 0 || board.get_winner().is_some() || board.is_ended() {
+        return 1;
+    }
+    let player = board.player_to_move();
+    board
+        .get_empty_spots()
+        .into_iter()
+        .map(|(row, col)| {
+            let mut next = *board;
+            next.make_move(player, row, col);
+            perft(&next, depth - 1)
+        })
+        .sum()
+}
+
+/// How many reachable positions there are, broken down a few ways
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateCounts {
+    pub total: usize,
+    /// `by_depth[d]` is how many positions have `d` pieces on the board
+    pub by_depth: Vec<usize>,
+    /// Positions where the game is not over, by who is to move
+    pub x_to_move: usize,
+    pub o_to_move: usize,
+    /// Finished games, by how they ended
+    pub x_won: usize,
+    pub o_won: usize,
+    pub ties: usize,
+}
+
+impl StateCounts {
+    /// Count every position reachable from `start`, optionally only once for every
+    /// set of positions the symmetries turn into each other
+    pub fn new<const N: usize>(start: Board<N>, modulo_symmetry: bool) -> Self {
+        let mut counts = StateCounts {
+            by_depth: vec![0; N * N + 1],
+            ..StateCounts::default()
+        };
+        for board in reachable(start, modulo_symmetry) {
+            counts.total += 1;
+            counts.by_depth[N * N - board.get_empty_spots().len()] += 1;
+            match (board.get_winner(), board.is_ended()) {
+                (Some(GameResult::XWon), _) => counts.x_won += 1,
+                (Some(GameResult::OWon), _) => counts.o_won += 1,
+                (Some(GameResult::Tie), _) | (None, true) => counts.ties += 1,
+                (None, false) => match board.player_to_move() {
+                    Player::X => counts.x_to_move += 1,
+                    Player::O => counts.o_to_move += 1,
+                },
+            }
+        }
+        counts
+    }
+}
+
+impl fmt::Display for StateCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} positions", self.total)?;
+        writeln!(f, "{:>6} {:>10}", "pieces", "positions")?;
+        for (depth, count) in self.by_depth.iter().enumerate() {
+            writeln!(f, "{depth:>6} {count:>10}")?;
+        }
+        writeln!(
+            f,
+            "In play: {} with X to move, {} with O to move",
+            self.x_to_move, self.o_to_move
+        )?;
+        writeln!(
+            f,
+            "Finished: {} won by X, {} won by O, {} tied",
+            self.x_won, self.o_won, self.ties
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tic_tac_toe::Piece;
+
+    #[test]
+    fn test_counts() {
+        let counts = StateCounts::new(Board::<3>::new(), false);
+        assert_eq!(5_478, counts.total);
+        assert_eq!(
+            vec![1, 9, 72, 252, 756, 1_260, 1_520, 1_140, 390, 78],
+            counts.by_depth
+        );
+        assert_eq!((626, 316, 16), (counts.x_won, counts.o_won, counts.ties));
+        assert_eq!(2_423, counts.x_to_move);
+        assert_eq!(2_423, decision_states(Board::<3>::new()).len());
+    }
+
+    #[test]
+    fn test_counts_modulo_symmetry() {
+        let counts = StateCounts::new(Board::<3>::new(), true);
+        assert_eq!(765, counts.total);
+        assert_eq!(
+            vec![1, 3, 12, 38, 108, 174, 204, 153, 57, 15],
+            counts.by_depth
+        );
+        assert_eq!((91, 44, 3), (counts.x_won, counts.o_won, counts.ties));
+    }
+
+    #[test]
+    fn test_perft() {
+        let board = Board::<3>::new();
+        let expected = [
+            1, 9, 72, 504, 3_024, 15_120, 56_160, 154_944, 255_168, 255_168,
+        ];
+        for (depth, &count) in expected.iter().enumerate() {
+            assert_eq!(count, perft(&board, depth), "depth {depth}");
+        }
+    }
+
+    #[test]
+    fn test_symmetries() {
+        let mut board = Board::<3>::new();
+        board.board[0][1] = Piece::X;
+        board.board[2][2] = Piece::O;
+        let images: FxHashSet<_> = (0..SYMMETRIES).map(|s| transform(s, &board)).collect();
+        assert_eq!(SYMMETRIES, images.len());
+        for sym in 0..SYMMETRIES {
+            let image = transform(sym, &board);
+            assert_eq!(canonical(&board).0, canonical(&image).0);
+            assert_eq!(
+                Piece::X,
+                image.board[map_cell::<3>(sym, (0, 1)).0][map_cell::<3>(sym, (0, 1)).1]
+            );
+            assert_eq!((0, 1), unmap_cell::<3>(sym, map_cell::<3>(sym, (0, 1))));
+        }
+        let (canon, sym) = canonical(&board);
+        assert_eq!(canon, transform(sym, &board));
+    }
+}
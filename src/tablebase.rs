@@ -0,0 +1,359 @@
+use std::fs;
+use std::path::Path;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rustc_hash::FxHashMap;
+
+use crate::error::{Error, Result};
+use crate::opponent::{Entry, Opponent, Outcome, PerfectPlay};
+use crate::states;
+use crate::tic_tac_toe::{Board, GameResult, Piece, Player};
+
+/// What a tablebase file starts with
+const MAGIC: &[u8; 4] = b"QTTB";
+const VERSION: u8 = 1;
+
+/// The perfect play value of every position reachable from the `start` it was
+/// built from, found once by searching the whole game and then looked up in
+/// constant time. Positions that are turns or flips of each other are stored once.
+pub struct Tablebase<const N: usize> {
+    /// How many in a row win
+    pub win_length: usize,
+    entries: FxHashMap<Board<N>, Entry>,
+    /// Breaks ties between equally good moves when playing
+    pub rng: StdRng,
+}
+
+impl<const N: usize> Tablebase<N> {
+    /// Solve every position reachable from `start`, which sets the win length.
+    /// Only practical for 3x3 boards, and 4x4 boards with a short win length.
+    pub fn build(start: Board<N>) -> Self {
+        let mut table = Tablebase {
+            win_length: start.n as usize,
+            entries: FxHashMap::default(),
+            rng: StdRng::seed_from_u64(0),
+        };
+        if !is_over(&start) {
+            table.solve(&states::canonical(&start).0);
+        }
+        table
+    }
+
+    /// Fill in the entry for `board`, which must be canonical and not over
+    fn solve(&mut self, board: &Board<N>) -> Entry {
+        if let Some(&entry) = self.entries.get(board) {
+            return entry;
+        }
+        let player = board.player_to_move();
+        let entry = board
+            .get_empty_spots()
+            .into_iter()
+            .map(|(row, col)| {
+                let mut next = *board;
+                match next.make_move(player, row, col) {
+                    Some(result) => end_entry(result),
+                    None => self.solve(&states::canonical(&next).0).before(),
+                }
+            })
+            .max_by_key(|entry| entry.key())
+            .expect("The game is not over");
+        self.entries.insert(*board, entry);
+        entry
+    }
+
+    /// How many positions are stored
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How `board` turns out for the player to move, or None if the game is over
+    /// or `board` can not come up from the `start` it was built from
+    pub fn probe(&self, board: &Board<N>) -> Option<Entry> {
+        if board.n as usize != self.win_length {
+            return None;
+        }
+        self.entries.get(&states::canonical(board).0).copied()
+    }
+
+    /// How every move the player to move can make in `board` turns out for them.
+    /// An error if the table is missing a position one of them leads to.
+    pub fn move_entries(&self, board: &Board<N>) -> Result<Vec<((usize, usize), Entry)>> {
+        let player = board.player_to_move();
+        board
+            .get_empty_spots()
+            .into_iter()
+            .map(|(row, col)| {
+                let mut next = *board;
+                let entry = match next.make_move(player, row, col) {
+                    Some(result) => end_entry(result),
+                    None => self.probe(&next).ok_or_else(|| missing(&next))?.before(),
+                };
+                Ok(((row, col), entry))
+            })
+            .collect()
+    }
+
+    /// The moves that do as well as possible, winning soonest or losing latest
+    pub fn best_moves(&self, board: &Board<N>) -> Result<Vec<(usize, usize)>> {
+        let entries = self.move_entries(board)?;
+        let best = entries.iter().map(|(_, entry)| entry.key()).max();
+        Ok(entries
+            .into_iter()
+            .filter(|(_, entry)| Some(entry.key()) == best)
+            .map(|(action, _)| action)
+            .collect())
+    }
+
+    /// Check that the table was built for `start`'s win length and holds `start`,
+    /// and so every position that can follow it
+    pub fn covers(&self, start: &Board<N>) -> Result<()> {
+        if start.n as usize != self.win_length {
+            return Err(Error::Config(format!(
+                "The tablebase is for {} in a row, not {}",
+                self.win_length, start.n
+            )));
+        }
+        if !is_over(start) && self.probe(start).is_none() {
+            return Err(missing(start));
+        }
+        Ok(())
+    }
+
+    /// Write the table in a compact binary format: a header, then every position
+    /// as eight little-endian bytes. The low 56 bits hold the board in base 3,
+    /// the next two the outcome and the top six the distance.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut out = Vec::with_capacity(11 + 8 * self.entries.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(N as u8);
+        out.push(self.win_length as u8);
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        // Sorted so the same table always makes the same file
+        let mut packed: Vec<u64> = self
+            .entries
+            .iter()
+            .map(|(board, entry)| {
+                let outcome = entry.outcome as u64;
+                encode(board) | outcome << 56 | u64::from(entry.distance) << 58
+            })
+            .collect();
+        packed.sort_unstable();
+        for value in packed {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Read a table written by `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let bad = |msg: &str| Error::Config(format!("Not a tablebase file: {msg}"));
+        let (header, body) = bytes.split_at_checked(11).ok_or_else(|| bad("too short"))?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(bad("wrong header"));
+        }
+        if header[5] as usize != N {
+            return Err(Error::Config(format!(
+                "Expected a tablebase for a {N}x{N} board, got {}x{}",
+                header[5], header[5]
+            )));
+        }
+        let win_length = header[6] as usize;
+        let count = u32::from_le_bytes(header[7..11].try_into().expect("Four bytes")) as usize;
+        if body.len() != 8 * count || !(2..=N).contains(&win_length) {
+            return Err(bad("wrong length"));
+        }
+
+        let mut entries = FxHashMap::default();
+        for chunk in body.chunks_exact(8) {
+            let value = u64::from_le_bytes(chunk.try_into().expect("Eight bytes"));
+            let outcome = match (value >> 56) & 0b11 {
+                0 => Outcome::Loss,
+                1 => Outcome::Draw,
+                2 => Outcome::Win,
+                _ => return Err(bad("unknown outcome")),
+            };
+            let board =
+                decode(value & ((1 << 56) - 1), win_length).ok_or_else(|| bad("bad board"))?;
+            board.validate()?;
+            let distance = (value >> 58) as u8;
+            entries.insert(board, Entry { outcome, distance });
+        }
+        Ok(Tablebase {
+            win_length,
+            entries,
+            rng: StdRng::seed_from_u64(0),
+        })
+    }
+}
+
+/// The error for a position the table does not hold, e.g. one outside the
+/// position it was built from
+fn missing<const N: usize>(board: &Board<N>) -> Error {
+    Error::Config(format!(
+        "The tablebase has no entry for {:?}; build it from an earlier position",
+        board.notation()
+    ))
+}
+
+fn is_over<const N: usize>(board: &Board<N>) -> bool {
+    board.get_winner().is_some() || board.is_ended()
+}
+
+/// The entry for the player whose move ended the game in `result`
+fn end_entry(result: GameResult) -> Entry {
+    Entry {
+        outcome: match result {
+            GameResult::Tie => Outcome::Draw,
+            // Only the player who just moved can have won
+            _ => Outcome::Win,
+        },
+        distance: 1,
+    }
+}
+
+/// The board as a number in base 3, one digit per cell row by row: 0 for empty,
+/// 1 for X and 2 for O
+fn encode<const N: usize>(board: &Board<N>) -> u64 {
+    board.board.iter().flatten().fold(0, |code, piece| {
+        let digit = match piece {
+            Piece::Empty => 0,
+            Piece::X => 1,
+            Piece::O => 2,
+        };
+        code * 3 + digit
+    })
+}
+
+/// Undoes `encode`
+fn decode<const N: usize>(mut code: u64, win_length: usize) -> Option<Board<N>> {
+    let mut board = Board::<N>::with_win_length(win_length);
+    for spot in board.board.iter_mut().flatten().rev() {
+        *spot = match code % 3 {
+            0 => Piece::Empty,
+            1 => Piece::X,
+            _ => Piece::O,
+        };
+        code /= 3;
+    }
+    (code == 0).then_some(board)
+}
+
+impl<const N: usize> PerfectPlay<N> for Tablebase<N> {
+    fn perfect_moves(
+        &mut self,
+        board: &Board<N>,
+        _player: Player,
+    ) -> Result<Vec<((usize, usize), Entry)>> {
+        self.move_entries(board)
+    }
+}
+
+/// Plays perfectly, winning as soon as it can. Check the table `covers` the start
+/// of every game first.
+impl<const N: usize> Opponent<N> for Tablebase<N> {
+    fn choose_move(&mut self, board: &Board<N>, _player: Player) -> (usize, usize) {
+        let best = self.best_moves(board).unwrap_or_else(|err| panic!("{err}"));
+        *best
+            .choose(&mut self.rng)
+            .expect("Failed to notice that the game was over")
+    }
+
+    fn annotate(
+        &self,
+        board: &Board<N>,
+        _player: Player,
+        action: (usize, usize),
+    ) -> Option<String> {
+        let (_, entry) = self
+            .move_entries(board)
+            .ok()?
+            .into_iter()
+            .find(|&(a, _)| a == action)?;
+        Some(entry.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::Solver;
+
+    #[test]
+    fn test_build() {
+        let table = Tablebase::build(Board::<3>::new());
+        // Every position with a move left, counting turns and flips once
+        assert_eq!(627, table.len());
+        let start = table.probe(&Board::new()).unwrap();
+        assert_eq!(Outcome::Draw, start.outcome);
+        assert_eq!(9, start.distance);
+    }
+
+    #[test]
+    fn test_agrees_with_solver() {
+        let table = Tablebase::build(Board::<3>::new());
+        let mut solver = Solver::<3>::new(0);
+        for board in states::reachable(Board::<3>::new(), false) {
+            if is_over(&board) {
+                assert_eq!(None, table.probe(&board));
+                continue;
+            }
+            let entry = table.probe(&board).unwrap();
+            let player = board.player_to_move();
+            assert_eq!(Outcome::of(solver.value(&board, player)), entry.outcome);
+            assert_eq!(
+                solver.perfect_moves(&board, player).unwrap(),
+                table.move_entries(&board).unwrap(),
+                "{board}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_quickest_win() {
+        // X can win now at (0, 2), or later
+        let board: Board<3> = "XX./O../..O x".parse().unwrap();
+        let table = Tablebase::build(Board::<3>::new());
+        assert_eq!(vec![(0, 2)], table.best_moves(&board).unwrap());
+        assert_eq!(
+            Some(Entry {
+                outcome: Outcome::Win,
+                distance: 1
+            }),
+            table.probe(&board)
+        );
+    }
+
+    #[test]
+    fn test_covers() {
+        let start: Board<3> = "X../.../... o".parse().unwrap();
+        let table = Tablebase::build(start);
+        assert!(table.covers(&start).is_ok());
+        assert!(table.covers(&"XO./.../... x".parse().unwrap()).is_ok());
+        // Not reachable from the start it was built from
+        let elsewhere: Board<3> = ".X./.../... o".parse().unwrap();
+        assert!(table.covers(&elsewhere).is_err());
+        assert!(table.move_entries(&elsewhere).is_err());
+        assert!(table.covers(&Board::with_win_length(2)).is_err());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let table = Tablebase::build(Board::<3>::with_win_length(2));
+        let path = std::env::temp_dir().join("qtictactoe_test_tablebase.bin");
+        table.save(&path).unwrap();
+        let loaded = Tablebase::<3>::load(&path).unwrap();
+        assert_eq!(2, loaded.win_length);
+        assert_eq!(table.entries, loaded.entries);
+        assert!(Tablebase::<4>::load(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
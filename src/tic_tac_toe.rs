@@ -1,6 +1,8 @@
 use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use crate::error::{Error, PositionError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Player {
     X,
     O,
@@ -13,9 +15,17 @@ impl Player {
             Player::O => Player::X,
         }
     }
+
+    /// The piece this player puts on the board
+    pub fn piece(self) -> Piece {
+        match self {
+            Player::X => Piece::X,
+            Player::O => Piece::O,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Piece {
     Empty = 0,
     X = 1,
@@ -33,7 +43,7 @@ impl fmt::Display for Piece {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GameResult {
     XWon,
     OWon,
@@ -43,6 +53,8 @@ pub enum GameResult {
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Board<const N: usize> {
     pub board: [[Piece; N]; N],
+    /// How many in a row are needed to win. This is N unless the board was made
+    /// with `with_win_length`
     pub n: i8,
 }
 
@@ -54,6 +66,15 @@ impl<const N: usize> Board<N> {
         }
     }
 
+    /// An empty board where `k` in a row (across, down or diagonally) wins
+    pub fn with_win_length(k: usize) -> Self {
+        assert!((2..=N).contains(&k), "Win length must be between 2 and N");
+        Board {
+            board: [[Piece::Empty; N]; N],
+            n: k.try_into().expect("k is too big to fit in an i8"),
+        }
+    }
+
     /// Check if a player has won in row `row_num`
     pub fn row_winner(&self, row_num: usize) -> Option<Player> {
         match self.board[row_num].iter().map(|p| *p as i8).sum::<i8>() {
@@ -79,15 +100,13 @@ impl<const N: usize> Board<N> {
 
     /// Iterate over the diagonal from top left to bottom right
     pub fn get_lr_diag(&self) -> impl Iterator<Item = Piece> + '_ {
-        (0_usize..N).into_iter().map(|idx| self.board[idx][idx])
+        (0_usize..N).map(|idx| self.board[idx][idx])
     }
 
     /// Iterate over the diagonal from top right to bottom left
     pub fn get_rl_diag(&self) -> impl Iterator<Item = Piece> + '_ {
         let last_idx = N - 1;
-        (0_usize..N)
-            .into_iter()
-            .map(move |idx| self.board[idx][last_idx - idx])
+        (0_usize..N).map(move |idx| self.board[idx][last_idx - idx])
     }
 
     /// Check if a player has won via a diagonal
@@ -109,8 +128,46 @@ impl<const N: usize> Board<N> {
         None
     }
 
+    /// Check for `n` in a row anywhere on the board. Only needed when `n` is
+    /// less than N, otherwise checking whole rows, columns and diagonals is enough
+    pub fn k_in_a_row_winner(&self) -> Option<Player> {
+        let k = self.n as isize;
+        let directions = [(0, 1), (1, 0), (1, 1), (1, -1)];
+        for row in 0..N {
+            for col in 0..N {
+                let piece = self.board[row][col];
+                if piece == Piece::Empty {
+                    continue;
+                }
+                for (d_row, d_col) in directions {
+                    let in_a_row = (1..k).all(|step| {
+                        let r = row as isize + d_row * step;
+                        let c = col as isize + d_col * step;
+                        (0..N as isize).contains(&r)
+                            && (0..N as isize).contains(&c)
+                            && self.board[r as usize][c as usize] == piece
+                    });
+                    if in_a_row {
+                        return match piece {
+                            Piece::X => Some(Player::X),
+                            _ => Some(Player::O),
+                        };
+                    }
+                }
+            }
+        }
+        None
+    }
+
     /// Return winner or tie if game over, otherwise None
     pub fn get_winner(&self) -> Option<GameResult> {
+        if (self.n as usize) < N {
+            return self.k_in_a_row_winner().map(|winner| match winner {
+                Player::X => GameResult::XWon,
+                Player::O => GameResult::OWon,
+            });
+        }
+
         // Check rows
         for row_num in 0_usize..N {
             // If there is a winner, return a GameResult
@@ -143,11 +200,68 @@ impl<const N: usize> Board<N> {
         None
     }
 
+    /// Whether `player` has a line, whatever the other player has
+    pub fn has_won(&self, player: Player) -> bool {
+        let mut own = *self;
+        for piece in own.board.iter_mut().flatten() {
+            if *piece != player.piece() {
+                *piece = Piece::Empty;
+            }
+        }
+        own.get_winner().is_some()
+    }
+
+    /// Check that this position can come up in a game where X moves first and the
+    /// players take turns
+    ///
+    /// ```
+    /// use qtictactoe::PositionError;
+    /// use qtictactoe::{Board, Piece};
+    ///
+    /// let mut board = Board::<3>::new();
+    /// board.board[0][0] = Piece::X;
+    /// assert_eq!(Ok(()), board.validate());
+    /// board.board[0][1] = Piece::X;
+    /// assert_eq!(Err(PositionError::PieceCount { x: 2, o: 0 }), board.validate());
+    /// ```
+    pub fn validate(&self) -> std::result::Result<(), PositionError> {
+        let (x, o) = (self.count(Piece::X), self.count(Piece::O));
+        if x != o && x != o + 1 {
+            return Err(PositionError::PieceCount { x, o });
+        }
+        match (self.has_won(Player::X), self.has_won(Player::O)) {
+            (true, true) => Err(PositionError::BothWon),
+            // X moved last exactly when it has one more piece
+            (true, false) if x == o => {
+                Err(PositionError::WinnerDidNotMoveLast { winner: Player::X })
+            }
+            (false, true) if x != o => {
+                Err(PositionError::WinnerDidNotMoveLast { winner: Player::O })
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Checks if all the spots are filled
     pub fn is_ended(&self) -> bool {
         !self.board.iter().flatten().any(|&p| p == Piece::Empty)
     }
 
+    /// How many of `piece` are on the board
+    pub fn count(&self, piece: Piece) -> usize {
+        self.board.iter().flatten().filter(|&&p| p == piece).count()
+    }
+
+    /// Whose turn it is. X always moves first, so it is X's turn whenever both
+    /// players have the same number of pieces on the board
+    pub fn player_to_move(&self) -> Player {
+        if self.count(Piece::X) == self.count(Piece::O) {
+            Player::X
+        } else {
+            Player::O
+        }
+    }
+
     /// Finds the positions of empty spots
     pub fn get_empty_spots(&self) -> Vec<(usize, usize)> {
         let mut empty_spots = Vec::new();
@@ -161,6 +275,49 @@ impl<const N: usize> Board<N> {
         empty_spots
     }
 
+    /// An empty spot that would win the game for `player`, if there is one
+    pub fn winning_move(&self, player: Player) -> Option<(usize, usize)> {
+        let target = match player {
+            Player::X => GameResult::XWon,
+            Player::O => GameResult::OWon,
+        };
+        self.get_empty_spots().into_iter().find(|&(row, col)| {
+            let mut next = *self;
+            next.make_move(player, row, col) == Some(target)
+        })
+    }
+
+    /// Like `make_move`, but an error instead of a panic if the spot is off the
+    /// board or taken, or the game is over
+    ///
+    /// ```
+    /// use qtictactoe::{Board, Error, Player};
+    ///
+    /// let mut board = Board::<3>::new();
+    /// assert!(board.try_move(Player::X, 1, 1).is_ok());
+    /// assert!(matches!(
+    ///     board.try_move(Player::O, 1, 1),
+    ///     Err(Error::IllegalMove { row: 1, col: 1 })
+    /// ));
+    /// ```
+    pub fn try_move(
+        &mut self,
+        player: Player,
+        row_num: usize,
+        col_num: usize,
+    ) -> Result<Option<GameResult>> {
+        if self.get_winner().is_some() || self.is_ended() {
+            return Err(Error::GameOver);
+        }
+        if row_num >= N || col_num >= N || self.board[row_num][col_num] != Piece::Empty {
+            return Err(Error::IllegalMove {
+                row: row_num,
+                col: col_num,
+            });
+        }
+        Ok(self.make_move(player, row_num, col_num))
+    }
+
     /// `player` makes a move. If it wins the game, return that, then check for tie,
     /// otherwise None
     pub fn make_move(
@@ -189,6 +346,12 @@ impl<const N: usize> Board<N> {
     }
 }
 
+impl<const N: usize> Default for Board<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<const N: usize> fmt::Display for Board<N> {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -443,7 +606,7 @@ mod tests {
         b.board[2][2] = Piece::X;
         let want = [Piece::Empty, Piece::Empty, Piece::X];
 
-        for (g, w) in b.get_lr_diag().zip(want.into_iter()) {
+        for (g, w) in b.get_lr_diag().zip(want) {
             assert_eq!(w, g)
         }
     }
@@ -462,7 +625,7 @@ mod tests {
         b.board[1][1] = Piece::X;
         let want = [Piece::Empty, Piece::X, Piece::Empty];
 
-        for (g, w) in b.get_rl_diag().zip(want.into_iter()) {
+        for (g, w) in b.get_rl_diag().zip(want) {
             assert_eq!(w, g)
         }
     }
@@ -475,7 +638,7 @@ mod tests {
         let want = [Piece::Empty, Piece::X, Piece::O];
         println!("{}", b);
 
-        for (g, w) in b.get_rl_diag().zip(want.into_iter()) {
+        for (g, w) in b.get_rl_diag().zip(want) {
             dbg!(w, g);
             assert_eq!(w, g);
         }
@@ -538,6 +701,36 @@ mod tests {
         assert!(b.is_ended())
     }
 
+    #[test]
+    fn test_k_in_a_row() {
+        let mut b = Board::<4>::with_win_length(3);
+        b.board[1][1] = Piece::O;
+        b.board[2][2] = Piece::O;
+        assert_eq!(None, b.get_winner());
+        b.board[3][3] = Piece::O;
+        assert_eq!(Some(GameResult::OWon), b.get_winner());
+
+        let mut b = Board::<4>::with_win_length(3);
+        b.board[0][3] = Piece::X;
+        b.board[1][2] = Piece::X;
+        b.board[2][1] = Piece::X;
+        assert_eq!(Some(GameResult::XWon), b.get_winner());
+        // A full row with mixed pieces is not a win
+        let mut b = Board::<4>::with_win_length(2);
+        b.board[0] = [Piece::X, Piece::O, Piece::X, Piece::O];
+        assert_eq!(None, b.get_winner());
+    }
+
+    #[test]
+    fn test_winning_move() {
+        let mut b = Board::<3>::new();
+        b.board[0][0] = Piece::X;
+        b.board[1][1] = Piece::X;
+        b.board[0][1] = Piece::O;
+        assert_eq!(Some((2, 2)), b.winning_move(Player::X));
+        assert_eq!(None, b.winning_move(Player::O));
+    }
+
     #[test]
     fn test_get_valid_actions() {
         let b = Board::<3>::new();
@@ -568,4 +761,62 @@ mod tests {
         }
         assert_eq!(want, b.get_empty_spots());
     }
+
+    #[test]
+    fn test_try_move() {
+        let mut b = Board::<3>::new();
+        assert!(matches!(
+            b.try_move(Player::X, 3, 0),
+            Err(Error::IllegalMove { row: 3, col: 0 })
+        ));
+        for col in 0..2 {
+            b.try_move(Player::X, 0, col).unwrap();
+            b.try_move(Player::O, 1, col).unwrap();
+        }
+        assert_eq!(Some(GameResult::XWon), b.try_move(Player::X, 0, 2).unwrap());
+        assert!(matches!(b.try_move(Player::O, 2, 2), Err(Error::GameOver)));
+    }
+
+    /// A 3x3 board from rows of `X`, `O` and `.`, without any checks
+    fn from_rows(rows: [&str; 3]) -> Board<3> {
+        let mut b = Board::<3>::new();
+        for (row, text) in rows.iter().enumerate() {
+            for (col, c) in text.chars().enumerate() {
+                b.board[row][col] = match c {
+                    'X' => Piece::X,
+                    'O' => Piece::O,
+                    _ => Piece::Empty,
+                };
+            }
+        }
+        b
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Ok(()), Board::<3>::new().validate());
+        assert_eq!(Ok(()), from_rows(["XXX", "OO.", "..."]).validate());
+        assert_eq!(Ok(()), from_rows(["XXX", "OOX", "OXO"]).validate());
+        assert_eq!(Ok(()), from_rows(["OOO", "XX.", "X.."]).validate());
+        assert_eq!(
+            Err(PositionError::PieceCount { x: 3, o: 0 }),
+            from_rows(["XXX", "...", "..."]).validate()
+        );
+        assert_eq!(
+            Err(PositionError::PieceCount { x: 0, o: 1 }),
+            from_rows(["O..", "...", "..."]).validate()
+        );
+        assert_eq!(
+            Err(PositionError::BothWon),
+            from_rows(["XXX", "OOO", "..."]).validate()
+        );
+        assert_eq!(
+            Err(PositionError::WinnerDidNotMoveLast { winner: Player::X }),
+            from_rows(["XXX", "OO.", "O.."]).validate()
+        );
+        assert_eq!(
+            Err(PositionError::WinnerDidNotMoveLast { winner: Player::O }),
+            from_rows(["OOO", "XX.", "XX."]).validate()
+        );
+    }
 }
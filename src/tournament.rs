@@ -0,0 +1,312 @@
+use std::fmt;
+
+use crate::agent::Record;
+use crate::opponent::Opponent;
+use crate::tic_tac_toe::{Board, GameResult, Player};
+
+/// A player in a tournament
+pub struct Entrant<const N: usize> {
+    pub name: String,
+    pub player: Box<dyn Opponent<N> + Send>,
+}
+
+/// A player's rating on the Elo scale, with a 95% confidence interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub elo: f64,
+    /// Half the width of the confidence interval
+    pub margin: f64,
+}
+
+/// The results of a round robin
+pub struct Standings {
+    pub names: Vec<String>,
+    /// `crosstable[i][j]` is how player `i` did against player `j`
+    pub crosstable: Vec<Vec<Record>>,
+    pub ratings: Vec<Rating>,
+}
+
+/// Play one game from `start`, with `x` moving first
+pub fn play_game<const N: usize>(
+    start: Board<N>,
+    x: &mut dyn Opponent<N>,
+    o: &mut dyn Opponent<N>,
+) -> GameResult {
+    let mut board = start;
+    let mut player = board.player_to_move();
+    loop {
+        let mover: &mut dyn Opponent<N> = match player {
+            Player::X => &mut *x,
+            Player::O => &mut *o,
+        };
+        let action = mover.choose_move(&board, player);
+        if let Some(res) = board.make_move(player, action.0, action.1) {
+            return res;
+        }
+        player = player.next_player();
+    }
+}
+
+/// Play `games` games between every pair of `entrants`, swapping who moves first
+/// after every game, then rate everyone
+pub fn round_robin<const N: usize>(
+    entrants: &mut [Entrant<N>],
+    games: usize,
+    start: Board<N>,
+) -> Standings {
+    let n = entrants.len();
+    let mut crosstable = vec![vec![Record::default(); n]; n];
+    let pairs = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j)));
+    for (i, j) in pairs {
+        let (left, right) = entrants.split_at_mut(j);
+        let (a, b) = (&mut left[i].player, &mut right[0].player);
+        for game in 0..games {
+            // `a` is X in even games and O in odd ones
+            let (result, a_side) = if game % 2 == 0 {
+                (play_game(start, a.as_mut(), b.as_mut()), Player::X)
+            } else {
+                (play_game(start, b.as_mut(), a.as_mut()), Player::O)
+            };
+            crosstable[i][j].add(result, a_side);
+            crosstable[j][i].add(result, a_side.next_player());
+        }
+    }
+    let ratings = rate(&crosstable);
+    Standings {
+        names: entrants.iter().map(|e| e.name.clone()).collect(),
+        crosstable,
+        ratings,
+    }
+}
+
+/// Bradley-Terry ratings, fitted by minorisation-maximisation and shown on the Elo
+/// scale with an average of 1500. Draws count as half a win each. Every pair also
+/// gets one virtual draw, which keeps ratings finite for players who win or lose
+/// everything. The confidence intervals ignore the uncertainty in the other
+/// players' ratings, so they are a little too narrow.
+pub fn rate(crosstable: &[Vec<Record>]) -> Vec<Rating> {
+    let n = crosstable.len();
+    let games = |i: usize, j: usize| crosstable[i][j].games() as f64 + 1.0;
+    let points = |i: usize, j: usize| {
+        let r = crosstable[i][j];
+        r.wins as f64 + 0.5 * r.draws as f64 + 0.5
+    };
+
+    let mut strength = vec![1.0; n];
+    for _ in 0..1_000 {
+        let mut next: Vec<f64> = (0..n)
+            .map(|i| {
+                let won: f64 = (0..n).filter(|&j| j != i).map(|j| points(i, j)).sum();
+                let expected: f64 = (0..n)
+                    .filter(|&j| j != i)
+                    .map(|j| games(i, j) / (strength[i] + strength[j]))
+                    .sum();
+                if expected == 0.0 { 1.0 } else { won / expected }
+            })
+            .collect();
+        // Only the ratios matter, so keep the geometric mean at 1
+        let log_mean = next.iter().map(|s| s.ln()).sum::<f64>() / n.max(1) as f64;
+        next.iter_mut().for_each(|s| *s /= log_mean.exp());
+        let change = next
+            .iter()
+            .zip(&strength)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        strength = next;
+        if change < 1e-10 {
+            break;
+        }
+    }
+
+    let elo_per_log = 400.0 / std::f64::consts::LN_10;
+    (0..n)
+        .map(|i| {
+            let information: f64 = (0..n)
+                .filter(|&j| j != i)
+                .map(|j| {
+                    let p = strength[i] / (strength[i] + strength[j]);
+                    games(i, j) * p * (1.0 - p)
+                })
+                .sum();
+            Rating {
+                elo: 1500.0 + elo_per_log * strength[i].ln(),
+                margin: 1.96 * elo_per_log / information.sqrt(),
+            }
+        })
+        .collect()
+}
+
+/// `s` as a JSON string
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Standings {
+    /// Every player, best rated first
+    pub fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|&a, &b| self.ratings[b].elo.total_cmp(&self.ratings[a].elo));
+        order
+    }
+
+    /// Each player's results against everyone
+    pub fn totals(&self, player: usize) -> Record {
+        self.crosstable[player]
+            .iter()
+            .fold(Record::default(), |total, r| Record {
+                wins: total.wins + r.wins,
+                draws: total.draws + r.draws,
+                losses: total.losses + r.losses,
+            })
+    }
+
+    /// One line per player, best rated first
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("rank,player,elo,elo_low,elo_high,wins,draws,losses,score\n");
+        for (rank, i) in self.order().into_iter().enumerate() {
+            let (rating, total) = (self.ratings[i], self.totals(i));
+            // Quote names in the CSV way, by doubling quotes
+            let name = format!("\"{}\"", self.names[i].replace('"', "\"\""));
+            out.push_str(&format!(
+                "{},{},{:.1},{:.1},{:.1},{},{},{},{:.4}\n",
+                rank + 1,
+                name,
+                rating.elo,
+                rating.elo - rating.margin,
+                rating.elo + rating.margin,
+                total.wins,
+                total.draws,
+                total.losses,
+                total.score()
+            ));
+        }
+        out
+    }
+
+    /// The ratings and the whole crosstable
+    pub fn to_json(&self) -> String {
+        let players: Vec<String> = (0..self.names.len())
+            .map(|i| {
+                let (rating, total) = (self.ratings[i], self.totals(i));
+                let results: Vec<String> = self.crosstable[i]
+                    .iter()
+                    .map(|r| format!("[{}, {}, {}]", r.wins, r.draws, r.losses))
+                    .collect();
+                format!(
+                    "    {{\"name\": {}, \"elo\": {:.1}, \"margin\": {:.1}, \"wins\": {}, \
+                     \"draws\": {}, \"losses\": {}, \"results\": [{}]}}",
+                    json_string(&self.names[i]),
+                    rating.elo,
+                    rating.margin,
+                    total.wins,
+                    total.draws,
+                    total.losses,
+                    results.join(", ")
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"results_are\": [\"wins\", \"draws\", \"losses\"],\n  \"players\": [\n{}\n  ]\n}}\n",
+            players.join(",\n")
+        )
+    }
+}
+
+/// The crosstable, with each cell as wins-draws-losses for the row's player, then
+/// the ratings
+impl fmt::Display for Standings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let order = self.order();
+        // Room for "12. " before each name
+        let width = self.names.iter().map(|n| n.len() + 4).max().unwrap_or(0);
+        write!(f, "{:width$}", "")?;
+        for col in 1..=order.len() {
+            write!(f, " {col:>11}")?;
+        }
+        writeln!(f, " {:>8} {:>6}", "elo", "+/-")?;
+        for (rank, &i) in order.iter().enumerate() {
+            write!(f, "{:width$}", format!("{}. {}", rank + 1, self.names[i]))?;
+            for &j in &order {
+                if i == j {
+                    write!(f, " {:>11}", "-")?;
+                } else {
+                    let r = self.crosstable[i][j];
+                    write!(f, " {:>11}", format!("{}-{}-{}", r.wins, r.draws, r.losses))?;
+                }
+            }
+            writeln!(
+                f,
+                " {:>8.0} {:>6.0}",
+                self.ratings[i].elo, self.ratings[i].margin
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opponent::{RandomOpponent, Solver};
+
+    #[test]
+    fn test_ratings_order() {
+        // A beats B 8-2, B beats C 8-2
+        let mut crosstable = vec![vec![Record::default(); 3]; 3];
+        let mut result = |i: usize, j: usize, wins, losses| {
+            crosstable[i][j] = Record {
+                wins,
+                draws: 0,
+                losses,
+            };
+            crosstable[j][i] = Record {
+                wins: losses,
+                draws: 0,
+                losses: wins,
+            };
+        };
+        result(0, 1, 8, 2);
+        result(1, 2, 8, 2);
+        let ratings = rate(&crosstable);
+        assert!(ratings[0].elo > ratings[1].elo && ratings[1].elo > ratings[2].elo);
+        let mean = ratings.iter().map(|r| r.elo).sum::<f64>() / 3.0;
+        assert!((mean - 1500.0).abs() < 100.0);
+        assert!(
+            ratings
+                .iter()
+                .all(|r| r.margin > 0.0 && r.margin.is_finite())
+        );
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut entrants = vec![
+            Entrant {
+                name: "random".to_string(),
+                player: Box::new(RandomOpponent::seeded(0)),
+            },
+            Entrant {
+                name: "solver".to_string(),
+                player: Box::new(Solver::<3>::new(0)),
+            },
+        ];
+        let standings = round_robin(&mut entrants, 20, Board::new());
+        assert_eq!(20, standings.crosstable[0][1].games());
+        assert_eq!(0, standings.crosstable[1][0].losses);
+        assert_eq!(vec![1, 0], standings.order());
+
+        let csv = standings.to_csv();
+        assert!(csv.lines().nth(1).unwrap().starts_with("1,\"solver\","));
+        assert!(standings.to_json().contains("\"name\": \"random\""));
+    }
+}